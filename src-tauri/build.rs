@@ -17,13 +17,34 @@ fn main() {
                 "add_custom_api",
                 "delete_custom_api",
                 "delete_custom_category",
-                "get_image_categories",
-                "get_current_image_category",
-                "set_current_image_category",
-                "add_custom_image_category",
-                "add_custom_image_api",
-                "delete_custom_image_api",
-                "delete_custom_image_category"
+                "export_api_catalog",
+                "import_api_catalog",
+                "preload_next_image",
+                "get_image_preload_count",
+                "pop_next_image",
+                "peek_next_image",
+                "clear_image_preload_queue",
+                "get_preload_settings",
+                "set_preload_settings",
+                "set_preload_paused",
+                "list_preload_queue",
+                "remove_preloaded_video",
+                "move_preloaded_to_front",
+                "pin_preloaded_video",
+                "history_back",
+                "history_forward",
+                "replay_history",
+                "search_history",
+                "clear_history",
+                "get_library_dir",
+                "set_library_dir",
+                "save_current_video",
+                "save_current_image",
+                "list_favorites",
+                "set_favorite_tags",
+                "remove_favorite",
+                "play_favorite",
+                "list_similar_favorites"
             ])),
     )
     .unwrap();
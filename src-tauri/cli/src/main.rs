@@ -0,0 +1,370 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use video_player_core::mime::{extension_for, resolve_content_type, SNIFF_LEN};
+use video_player_core::model::RANDOM_CATEGORY;
+use video_player_core::resolver::{download_media, resolve_endpoint};
+use video_player_core::settings::{read_settings, settings_path};
+use video_player_core::{Catalog, MediaKind, MediaSpec};
+
+/// 与 tauri.conf.json 中的 identifier 一致，以读取桌面端的设置
+const APP_IDENTIFIER: &str = "com.video-player.app";
+
+// ============================================================
+// 命令行参数
+// ============================================================
+
+/// 使用桌面端的接口配置批量解析、下载视频与图片
+#[derive(Parser)]
+#[command(name = "video-player-cli", version)]
+struct Cli {
+    /// 设置文件路径，默认与桌面端相同
+    #[arg(long, global = true)]
+    settings: Option<PathBuf>,
+
+    /// 以 JSON 输出，便于管道处理
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Media {
+    Video,
+    Image,
+}
+
+impl From<Media> for MediaKind {
+    fn from(media: Media) -> Self {
+        match media {
+            Media::Video => MediaKind::Video,
+            Media::Image => MediaKind::Image,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// 列出分类及其接口
+    Categories {
+        /// 媒体类型
+        #[arg(short, long, value_enum, default_value = "video")]
+        media: Media,
+    },
+    /// 解析出 N 个媒体地址
+    Resolve {
+        #[arg(short, long, value_enum, default_value = "video")]
+        media: Media,
+        /// 分类 id，默认使用桌面端当前选中的分类
+        #[arg(short, long)]
+        category: Option<String>,
+        #[arg(short = 'n', long, default_value_t = 1)]
+        count: usize,
+    },
+    /// 解析并下载 N 个媒体文件
+    Download {
+        #[arg(short, long, value_enum, default_value = "video")]
+        media: Media,
+        /// 分类 id，默认使用桌面端当前选中的分类
+        #[arg(short, long)]
+        category: Option<String>,
+        #[arg(short = 'n', long, default_value_t = 1)]
+        count: usize,
+        /// 保存目录
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+        /// 文件名模板，可用 {media} {category} {index} {name} {ext}
+        #[arg(short, long, default_value = "{media}_{category}_{index}.{ext}")]
+        template: String,
+        /// 覆盖已存在的文件
+        #[arg(long)]
+        overwrite: bool,
+    },
+}
+
+// ============================================================
+// 辅助函数
+// ============================================================
+
+fn media_name(kind: MediaKind) -> &'static str {
+    match kind {
+        MediaKind::Video => "video",
+        MediaKind::Image => "image",
+    }
+}
+
+/// 指定分类时只在内存中切换，不写回设置文件
+fn select_category(
+    catalog: &mut Catalog,
+    kind: MediaKind,
+    category: Option<String>,
+) -> Result<String, String> {
+    if let Some(category) = category {
+        catalog.set_current(kind.spec(), category)?;
+    }
+    Ok(catalog.current_category.clone())
+}
+
+/// 从当前分类中随机挑选接口并解析出媒体地址
+async fn resolve_url(catalog: &Catalog, kind: MediaKind) -> Result<String, String> {
+    let endpoint = catalog.pick_endpoint(kind.spec())?;
+    resolve_endpoint(&endpoint, kind.spec()).await
+}
+
+/// URL 路径中的文件名，去掉可识别的媒体扩展名
+fn file_stem(url: &str, spec: &MediaSpec) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let file = path.rsplit('/').next().unwrap_or_default();
+    let lower = file.to_ascii_lowercase();
+
+    let stem = spec
+        .url_extensions
+        .iter()
+        .find(|ext| lower.ends_with(*ext))
+        .map_or(file, |ext| &file[..file.len() - ext.len()]);
+    if stem.is_empty() {
+        "media".to_string()
+    } else {
+        stem.to_string()
+    }
+}
+
+/// 与桌面端保存收藏时一致：按文件头识别类型再决定扩展名
+fn file_ext(data: &[u8], spec: &MediaSpec) -> &'static str {
+    let content_type = resolve_content_type(
+        None,
+        &data[..data.len().min(SNIFF_LEN)],
+        spec.default_content_type,
+    );
+    extension_for(&content_type).unwrap_or("bin")
+}
+
+/// 不覆盖时以 `create_new` 打开，避免检查与写入之间被其他进程抢先创建
+fn write_file(path: &Path, data: &[u8], overwrite: bool) -> Result<(), String> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true);
+    if overwrite {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    let mut file = options.open(path).map_err(|e| match e.kind() {
+        ErrorKind::AlreadyExists => format!("文件已存在: {}", path.display()),
+        _ => format!("写入失败: {}", e),
+    })?;
+    file.write_all(data).map_err(|e| format!("写入失败: {}", e))
+}
+
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+fn render_template(template: &str, fields: &[(&str, String)]) -> String {
+    fields
+        .iter()
+        .fold(template.to_string(), |name, (key, value)| {
+            name.replace(&format!("{{{}}}", key), &sanitize(value))
+        })
+}
+
+fn print_results(json: bool, results: &[Value]) {
+    if json {
+        println!("{}", Value::Array(results.to_vec()));
+        return;
+    }
+    for result in results {
+        match result.get("error").and_then(|e| e.as_str()) {
+            Some(error) => eprintln!("#{} 失败: {}", result["index"], error),
+            None => match result.get("path").and_then(|p| p.as_str()) {
+                Some(path) => println!("{}\t{}", path, result["url"].as_str().unwrap_or_default()),
+                None => println!("{}", result["url"].as_str().unwrap_or_default()),
+            },
+        }
+    }
+}
+
+// ============================================================
+// 子命令
+// ============================================================
+
+fn list_categories(catalog: &Catalog, kind: MediaKind, json: bool) {
+    let categories = catalog.categories(kind.spec());
+
+    if json {
+        println!("{}", json!(categories));
+        return;
+    }
+    let marker = |id: &str| {
+        if id == catalog.current_category {
+            " *"
+        } else {
+            ""
+        }
+    };
+    println!(
+        "{}\t随机（全部接口）{}",
+        RANDOM_CATEGORY,
+        marker(RANDOM_CATEGORY)
+    );
+    for category in categories {
+        let current = marker(&category.id);
+        println!("{}\t{}{}", category.id, category.name, current);
+        for endpoint in category.endpoints {
+            println!("  {}\t{}\t{}", endpoint.id, endpoint.name, endpoint.url);
+        }
+    }
+}
+
+async fn resolve(catalog: &Catalog, kind: MediaKind, count: usize) -> Vec<Value> {
+    let mut results = Vec::with_capacity(count);
+    for index in 1..=count {
+        results.push(match resolve_url(catalog, kind).await {
+            Ok(url) => json!({ "index": index, "url": url }),
+            Err(e) => json!({ "index": index, "error": e }),
+        });
+    }
+    results
+}
+
+struct DownloadOptions {
+    output: PathBuf,
+    template: String,
+    overwrite: bool,
+}
+
+async fn download(
+    catalog: &Catalog,
+    kind: MediaKind,
+    category: &str,
+    count: usize,
+    options: &DownloadOptions,
+) -> Vec<Value> {
+    let mut results = Vec::with_capacity(count);
+    for index in 1..=count {
+        let url = match resolve_url(catalog, kind).await {
+            Ok(url) => url,
+            Err(e) => {
+                results.push(json!({ "index": index, "error": e }));
+                continue;
+            }
+        };
+
+        // 扩展名取决于下载到的内容，文件名要在下载后才能确定
+        let result = async {
+            let data = download_media(kind, &url).await?;
+            let file_name = render_template(
+                &options.template,
+                &[
+                    ("media", media_name(kind).to_string()),
+                    ("category", category.to_string()),
+                    ("index", index.to_string()),
+                    ("name", file_stem(&url, kind.spec())),
+                    ("ext", file_ext(&data, kind.spec()).to_string()),
+                ],
+            );
+            let path = options.output.join(file_name);
+            write_file(&path, &data, options.overwrite)?;
+            Ok::<_, String>((path, data.len()))
+        }
+        .await;
+
+        results.push(match result {
+            Ok((path, bytes)) => json!({
+                "index": index,
+                "url": url,
+                "path": path.display().to_string(),
+                "bytes": bytes,
+            }),
+            Err(e) => json!({ "index": index, "url": url, "error": e }),
+        });
+    }
+    results
+}
+
+// ============================================================
+// Main
+// ============================================================
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    // 只读取分类目录，不加载播放记录、收藏与缓存；设置文件无法解析时直接报错，不改名备份
+    let settings_file = cli
+        .settings
+        .unwrap_or_else(|| settings_path(APP_IDENTIFIER));
+    let mut catalogs: BTreeMap<MediaKind, Catalog> = match read_settings(&settings_file) {
+        Ok(settings) => settings.catalogs,
+        Err(e) => {
+            eprintln!("读取设置失败: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let results = match cli.command {
+        Command::Categories { media } => {
+            let kind = media.into();
+            list_categories(catalogs.entry(kind).or_default(), kind, cli.json);
+            return ExitCode::SUCCESS;
+        }
+        Command::Resolve {
+            media,
+            category,
+            count,
+        } => {
+            let kind = media.into();
+            let catalog = catalogs.entry(kind).or_default();
+            if let Err(e) = select_category(catalog, kind, category) {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+            resolve(catalog, kind, count).await
+        }
+        Command::Download {
+            media,
+            category,
+            count,
+            output,
+            template,
+            overwrite,
+        } => {
+            let kind = media.into();
+            let catalog = catalogs.entry(kind).or_default();
+            let category = match select_category(catalog, kind, category) {
+                Ok(category) => category,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            if let Err(e) = fs::create_dir_all(&output) {
+                eprintln!("创建目录失败: {}", e);
+                return ExitCode::FAILURE;
+            }
+            let options = DownloadOptions {
+                output,
+                template,
+                overwrite,
+            };
+            download(catalog, kind, &category, count, &options).await
+        }
+    };
+
+    print_results(cli.json, &results);
+    if results.iter().any(|r| r.get("error").is_some()) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
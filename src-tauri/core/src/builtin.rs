@@ -0,0 +1,176 @@
+use crate::model::{ApiCategory, ApiEndpoint, ResponseKind};
+
+pub fn get_builtin_categories() -> Vec<ApiCategory> {
+    vec![
+        ApiCategory {
+            id: "taozi".into(),
+            name: "桃子API".into(),
+            builtin: true,
+            endpoints: vec![
+                ApiEndpoint {
+                    id: "taozi_1".into(),
+                    name: "抖音快手美女".into(),
+                    url: "https://api.tzjsy.cn/sp/dyksmn/video.php".into(),
+                    builtin: true,
+                    extract: None,
+                    kind: ResponseKind::Auto,
+                },
+                ApiEndpoint {
+                    id: "taozi_2".into(),
+                    name: "黑丝".into(),
+                    url: "https://api.tzjsy.cn/sp/hs/video.php".into(),
+                    builtin: true,
+                    extract: None,
+                    kind: ResponseKind::Auto,
+                },
+                ApiEndpoint {
+                    id: "taozi_3".into(),
+                    name: "白丝".into(),
+                    url: "https://api.tzjsy.cn/sp/bs/video.php".into(),
+                    builtin: true,
+                    extract: None,
+                    kind: ResponseKind::Auto,
+                },
+                ApiEndpoint {
+                    id: "taozi_4".into(),
+                    name: "JK制服".into(),
+                    url: "https://api.tzjsy.cn/sp/jk/video.php".into(),
+                    builtin: true,
+                    extract: None,
+                    kind: ResponseKind::Auto,
+                },
+                ApiEndpoint {
+                    id: "taozi_5".into(),
+                    name: "甜美".into(),
+                    url: "https://api.tzjsy.cn/sp/tm/video.php".into(),
+                    builtin: true,
+                    extract: None,
+                    kind: ResponseKind::Auto,
+                },
+                ApiEndpoint {
+                    id: "taozi_6".into(),
+                    name: "纯欲".into(),
+                    url: "https://api.tzjsy.cn/sp/cy/video.php".into(),
+                    builtin: true,
+                    extract: None,
+                    kind: ResponseKind::Auto,
+                },
+                ApiEndpoint {
+                    id: "taozi_7".into(),
+                    name: "QC".into(),
+                    url: "https://api.tzjsy.cn/sp/qc/video.php".into(),
+                    builtin: true,
+                    extract: None,
+                    kind: ResponseKind::Auto,
+                },
+                ApiEndpoint {
+                    id: "taozi_8".into(),
+                    name: "LL".into(),
+                    url: "https://api.tzjsy.cn/sp/ll/video.php".into(),
+                    builtin: true,
+                    extract: None,
+                    kind: ResponseKind::Auto,
+                },
+                ApiEndpoint {
+                    id: "taozi_9".into(),
+                    name: "YZ".into(),
+                    url: "https://api.tzjsy.cn/sp/yz/video.php".into(),
+                    builtin: true,
+                    extract: None,
+                    kind: ResponseKind::Auto,
+                },
+                ApiEndpoint {
+                    id: "taozi_10".into(),
+                    name: "515".into(),
+                    url: "https://api.tzjsy.cn/sp/515/video.php".into(),
+                    builtin: true,
+                    extract: None,
+                    kind: ResponseKind::Auto,
+                },
+                ApiEndpoint {
+                    id: "taozi_11".into(),
+                    name: "COS".into(),
+                    url: "https://api.tzjsy.cn/sp/cos/video.php".into(),
+                    builtin: true,
+                    extract: None,
+                    kind: ResponseKind::Auto,
+                },
+                ApiEndpoint {
+                    id: "taozi_12".into(),
+                    name: "YOZ".into(),
+                    url: "https://api.tzjsy.cn/sp/yoz/video.php".into(),
+                    builtin: true,
+                    extract: None,
+                    kind: ResponseKind::Auto,
+                },
+            ],
+        },
+        ApiCategory {
+            id: "wanfeng".into(),
+            name: "晚风API".into(),
+            builtin: true,
+            endpoints: vec![ApiEndpoint {
+                id: "wanfeng_1".into(),
+                name: "跳舞视频".into(),
+                url: "http://api.nonebot.top/api/v1/random/dance_video".into(),
+                builtin: true,
+                extract: None,
+                kind: ResponseKind::Auto,
+            }],
+        },
+        ApiCategory {
+            id: "huai".into(),
+            name: "Huai API".into(),
+            builtin: true,
+            endpoints: vec![ApiEndpoint {
+                id: "huai_1".into(),
+                name: "抖音视频".into(),
+                url: "http://api.huaiyan.top:81/api/dy?type=mp4".into(),
+                builtin: true,
+                extract: None,
+                kind: ResponseKind::Auto,
+            }],
+        },
+    ]
+}
+
+pub fn get_builtin_image_categories() -> Vec<ApiCategory> {
+    vec![
+        ApiCategory {
+            id: "btstu".into(),
+            name: "BTSTU美图".into(),
+            builtin: true,
+            endpoints: vec![
+                ApiEndpoint {
+                    id: "btstu_1".into(),
+                    name: "随机美女(直连)".into(),
+                    url: "https://api.btstu.cn/sjbz/api.php?lx=meizi".into(),
+                    builtin: true,
+                    extract: None,
+                    kind: ResponseKind::Auto,
+                },
+                ApiEndpoint {
+                    id: "btstu_2".into(),
+                    name: "随机美女(JSON)".into(),
+                    url: "https://api.btstu.cn/sjbz/api.php?lx=meizi&format=json".into(),
+                    builtin: true,
+                    extract: None,
+                    kind: ResponseKind::Auto,
+                },
+            ],
+        },
+        ApiCategory {
+            id: "wanfeng".into(),
+            name: "晚风API".into(),
+            builtin: true,
+            endpoints: vec![ApiEndpoint {
+                id: "wanfeng_1".into(),
+                name: "晚风api".into(),
+                url: "http://api.nonebot.top/api/v1/random/tuwan".into(),
+                builtin: true,
+                extract: None,
+                kind: ResponseKind::Auto,
+            }],
+        },
+    ]
+}
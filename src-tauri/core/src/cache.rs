@@ -0,0 +1,369 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::media::MB;
+
+/// 预加载缓存目录的默认容量
+pub const PRELOAD_CACHE_BUDGET: u64 = 400 * MB;
+
+/// 图片缓存目录的默认容量
+pub const IMAGE_CACHE_BUDGET: u64 = 100 * MB;
+
+struct CacheEntry {
+    key: String,
+    len: u64,
+}
+
+/// 按 URL 缓存媒体文件的磁盘目录，超出容量时淘汰最久未使用的文件
+///
+/// 缓存只在本次运行内有效，打开时会清空目录中的旧文件。
+pub struct DiskCache {
+    dir: PathBuf,
+    budget: AtomicU64,
+    /// 越靠后越是最近使用
+    entries: Mutex<Vec<CacheEntry>>,
+    /// 正在写入的 key 与已写入的字节数，同一 key 同时只允许一个写入者
+    writing: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+/// 缓存中的一个文件
+#[derive(Clone)]
+pub struct CachedFile {
+    pub key: String,
+    pub path: PathBuf,
+    pub len: u64,
+}
+
+impl DiskCache {
+    /// 目录在第一次写入时才创建
+    pub fn open(dir: PathBuf, budget: u64) -> Self {
+        if dir.exists() {
+            if let Err(e) = remove_dir_contents(&dir) {
+                eprintln!("cache: 清理 {} 失败: {}", dir.display(), e);
+            }
+        }
+        Self {
+            dir,
+            budget: AtomicU64::new(budget),
+            entries: Mutex::new(Vec::new()),
+            writing: Arc::default(),
+        }
+    }
+
+    pub fn key_for(url: &str) -> String {
+        let mut hasher = DefaultHasher::new();
+        url.hash(&mut hasher);
+        format!("{:016x}", hasher.finish())
+    }
+
+    fn path_for(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.bin", key))
+    }
+
+    pub fn budget(&self) -> u64 {
+        self.budget.load(Ordering::SeqCst)
+    }
+
+    /// 调小后在下一次淘汰时生效
+    pub fn set_budget(&self, budget: u64) {
+        self.budget.store(budget, Ordering::SeqCst);
+    }
+
+    pub fn total_len(&self) -> u64 {
+        self.entries.lock().unwrap().iter().map(|e| e.len).sum()
+    }
+
+    /// 尚未提交的写入者已写入的字节数，不计入 [`DiskCache::total_len`]
+    pub fn writing_len(&self) -> u64 {
+        self.writing.lock().unwrap().values().sum()
+    }
+
+    /// 已缓存文件的长度，不影响淘汰顺序
+    pub fn len_of(&self, key: &str) -> Option<u64> {
+        let entries = self.entries.lock().unwrap();
+        entries.iter().find(|e| e.key == key).map(|e| e.len)
+    }
+
+    /// 命中时标记为最近使用
+    pub fn get(&self, key: &str) -> Option<CachedFile> {
+        let mut entries = self.entries.lock().unwrap();
+        let index = entries.iter().position(|e| e.key == key)?;
+        let entry = entries.remove(index);
+        let file = CachedFile {
+            key: entry.key.clone(),
+            path: self.path_for(key),
+            len: entry.len,
+        };
+        entries.push(entry);
+        Some(file)
+    }
+
+    /// 先写临时文件再改名，避免读到写了一半的文件
+    pub fn insert(&self, key: &str, data: &[u8]) -> Result<CachedFile, String> {
+        fs::create_dir_all(&self.dir).map_err(|e| format!("创建缓存目录失败: {}", e))?;
+
+        let path = self.path_for(key);
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut file =
+                fs::File::create(&tmp_path).map_err(|e| format!("写入缓存失败: {}", e))?;
+            file.write_all(data)
+                .map_err(|e| format!("写入缓存失败: {}", e))?;
+        }
+        fs::rename(&tmp_path, &path).map_err(|e| format!("写入缓存失败: {}", e))?;
+
+        let len = data.len() as u64;
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|e| e.key != key);
+        entries.push(CacheEntry {
+            key: key.to_string(),
+            len,
+        });
+
+        Ok(CachedFile {
+            key: key.to_string(),
+            path,
+            len,
+        })
+    }
+
+    /// 直接写入最终路径，便于边下载边读取；完成后需调用 [`DiskCache::commit`]
+    ///
+    /// 该 key 已缓存或正在被另一个写入者写入时返回 `None`，避免截断别人正在读取的文件。
+    pub fn create(&self, key: &str) -> Result<Option<CacheWriter>, String> {
+        let entries = self.entries.lock().unwrap();
+        let mut writing = self.writing.lock().unwrap();
+        if entries.iter().any(|e| e.key == key) || writing.contains_key(key) {
+            return Ok(None);
+        }
+        writing.insert(key.to_string(), 0);
+        drop(entries);
+        let lease = WriteLease {
+            key: key.to_string(),
+            writing: self.writing.clone(),
+        };
+        drop(writing);
+
+        fs::create_dir_all(&self.dir).map_err(|e| format!("创建缓存目录失败: {}", e))?;
+        let path = self.path_for(key);
+        let file = fs::File::create(&path).map_err(|e| format!("写入缓存失败: {}", e))?;
+        Ok(Some(CacheWriter {
+            key: key.to_string(),
+            path,
+            file,
+            len: 0,
+            lease,
+        }))
+    }
+
+    /// 登记写完的文件，此后才参与容量淘汰
+    pub fn commit(&self, mut writer: CacheWriter) -> Result<CachedFile, String> {
+        writer
+            .file
+            .flush()
+            .map_err(|e| format!("写入缓存失败: {}", e))?;
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|e| e.key != writer.key);
+        entries.push(CacheEntry {
+            key: writer.key.clone(),
+            len: writer.len,
+        });
+
+        Ok(CachedFile {
+            key: writer.key,
+            path: writer.path,
+            len: writer.len,
+        })
+    }
+
+    /// 丢弃写了一半的文件，删除后才释放该 key
+    pub fn abort(&self, writer: CacheWriter) {
+        let CacheWriter {
+            path, file, lease, ..
+        } = writer;
+        drop(file);
+        let _ = fs::remove_file(path);
+        drop(lease);
+    }
+
+    /// 淘汰最久未使用的文件直到不超过容量，`keep` 中的条目不会被淘汰
+    pub fn evict(&self, keep: &HashSet<String>) {
+        let mut entries = self.entries.lock().unwrap();
+        let mut total: u64 = entries.iter().map(|e| e.len).sum();
+        let budget = self.budget();
+
+        let mut index = 0;
+        while total > budget && index < entries.len() {
+            if keep.contains(&entries[index].key) {
+                index += 1;
+                continue;
+            }
+            let entry = entries.remove(index);
+            // 文件仍被占用时（如 Windows 上正在读取）保留，下次再试
+            if let Err(e) = fs::remove_file(self.path_for(&entry.key)) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    eprintln!("cache: 删除 {} 失败: {}", entry.key, e);
+                    entries.insert(index, entry);
+                    index += 1;
+                    continue;
+                }
+            }
+            total -= entry.len;
+        }
+    }
+
+    pub fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clear();
+        if self.dir.exists() {
+            if let Err(e) = remove_dir_contents(&self.dir) {
+                eprintln!("cache: 清理 {} 失败: {}", self.dir.display(), e);
+            }
+        }
+    }
+}
+
+/// 正在写入的缓存文件
+pub struct CacheWriter {
+    key: String,
+    path: PathBuf,
+    file: fs::File,
+    len: u64,
+    lease: WriteLease,
+}
+
+/// 占用一个 key 的写入权，提交、丢弃或中途出错时释放
+struct WriteLease {
+    key: String,
+    writing: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+impl WriteLease {
+    fn set_len(&self, len: u64) {
+        if let Some(written) = self.writing.lock().unwrap().get_mut(&self.key) {
+            *written = len;
+        }
+    }
+}
+
+impl Drop for WriteLease {
+    fn drop(&mut self) {
+        self.writing.lock().unwrap().remove(&self.key);
+    }
+}
+
+impl CacheWriter {
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> Result<(), String> {
+        self.file
+            .write_all(data)
+            .map_err(|e| format!("写入缓存失败: {}", e))?;
+        self.len += data.len() as u64;
+        self.lease.set_len(self.len);
+        Ok(())
+    }
+}
+
+fn remove_dir_contents(dir: &Path) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// 从文件中读取 `[start, start + len)` 区间
+pub fn read_file_range(path: &Path, start: u64, len: u64) -> std::io::Result<Vec<u8>> {
+    let mut file = fs::File::open(path)?;
+    file.seek(SeekFrom::Start(start))?;
+    let mut buf = Vec::with_capacity(len as usize);
+    file.take(len).read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_cache(budget: u64) -> DiskCache {
+        let dir = std::env::temp_dir().join(format!("cache-test-{}", uuid::Uuid::new_v4()));
+        DiskCache::open(dir, budget)
+    }
+
+    #[test]
+    fn refuses_second_writer_for_same_key() {
+        let cache = temp_cache(MB);
+        let mut writer = cache.create("a").unwrap().unwrap();
+        writer.write(b"first").unwrap();
+        assert!(cache.create("a").unwrap().is_none());
+
+        cache.abort(writer);
+        let mut writer = cache.create("a").unwrap().unwrap();
+        writer.write(b"second").unwrap();
+        let file = cache.commit(writer).unwrap();
+        assert_eq!(fs::read(&file.path).unwrap(), b"second");
+        assert!(cache.create("a").unwrap().is_none());
+        let _ = fs::remove_dir_all(&cache.dir);
+    }
+
+    #[test]
+    fn evicts_least_recently_used_except_kept() {
+        let cache = temp_cache(25);
+        for key in ["a", "b", "c"] {
+            cache.insert(key, &[0; 10]).unwrap();
+        }
+        // 命中后 a 变为最近使用，淘汰顺序为 b、c、a
+        assert!(cache.get("a").is_some());
+        cache.evict(&HashSet::from(["b".to_string()]));
+
+        assert_eq!(cache.total_len(), 20);
+        assert!(cache.len_of("a").is_some() && cache.len_of("b").is_some());
+        assert!(cache.len_of("c").is_none());
+        assert!(!cache.path_for("c").exists());
+
+        // 保留的条目超出容量时也不删除
+        cache.set_budget(0);
+        cache.evict(&HashSet::from(["b".to_string()]));
+        assert_eq!(cache.total_len(), 10);
+        assert!(cache.get("b").is_some());
+        let _ = fs::remove_dir_all(&cache.dir);
+    }
+
+    #[test]
+    fn counts_bytes_of_active_writers() {
+        let cache = temp_cache(MB);
+        let mut writer = cache.create("a").unwrap().unwrap();
+        writer.write(&[0; 10]).unwrap();
+        assert_eq!(cache.writing_len(), 10);
+        assert_eq!(cache.total_len(), 0);
+
+        cache.commit(writer).unwrap();
+        assert_eq!(cache.writing_len(), 0);
+        assert_eq!(cache.total_len(), 10);
+        let _ = fs::remove_dir_all(&cache.dir);
+    }
+}
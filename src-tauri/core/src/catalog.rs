@@ -0,0 +1,528 @@
+use rand::seq::SliceRandom;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use crate::extract::normalize_extract_rule;
+use crate::media::{MediaKind, MediaSpec};
+use crate::model::{
+    now_millis, ApiCategory, ApiEndpoint, ExtractRule, ResponseKind, RANDOM_CATEGORY,
+};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CustomEndpoint {
+    pub category_id: String,
+    pub endpoint: ApiEndpoint,
+}
+
+/// 某一媒体类型的自定义分类、自定义接口与当前选择
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Catalog {
+    pub current_category: String,
+    pub custom_categories: Vec<ApiCategory>,
+    pub custom_endpoints: Vec<CustomEndpoint>,
+}
+
+impl Default for Catalog {
+    fn default() -> Self {
+        Self {
+            current_category: RANDOM_CATEGORY.into(),
+            custom_categories: Vec::new(),
+            custom_endpoints: Vec::new(),
+        }
+    }
+}
+
+impl Catalog {
+    fn endpoints_of(&self, category_id: &str) -> impl Iterator<Item = &ApiEndpoint> + '_ {
+        let category_id = category_id.to_string();
+        self.custom_endpoints
+            .iter()
+            .filter(move |e| e.category_id == category_id)
+            .map(|e| &e.endpoint)
+    }
+
+    /// 内置分类 + 自定义分类，自定义接口挂到各自分类下
+    pub fn categories(&self, spec: &MediaSpec) -> Vec<ApiCategory> {
+        let mut categories = (spec.builtin_categories)();
+        categories.extend(self.custom_categories.iter().cloned());
+        for cat in categories.iter_mut() {
+            let custom: Vec<ApiEndpoint> = self.endpoints_of(&cat.id).cloned().collect();
+            cat.endpoints.extend(custom);
+        }
+        categories
+    }
+
+    pub fn category_exists(&self, spec: &MediaSpec, category_id: &str) -> bool {
+        category_id == RANDOM_CATEGORY
+            || (spec.builtin_categories)()
+                .iter()
+                .any(|c| c.id == category_id)
+            || self.custom_categories.iter().any(|c| c.id == category_id)
+    }
+
+    /// 当前分类下可选的接口，随机模式下为全部接口
+    pub fn candidates(&self, spec: &MediaSpec) -> Vec<ApiEndpoint> {
+        let categories = self.categories(spec);
+        if self.current_category == RANDOM_CATEGORY {
+            categories.into_iter().flat_map(|c| c.endpoints).collect()
+        } else {
+            categories
+                .into_iter()
+                .find(|c| c.id == self.current_category)
+                .map(|c| c.endpoints)
+                .unwrap_or_default()
+        }
+    }
+
+    /// 从当前分类中随机挑选一个接口
+    pub fn pick_endpoint(&self, spec: &MediaSpec) -> Result<ApiEndpoint, String> {
+        let candidates = self.candidates(spec);
+        candidates
+            .choose(&mut rand::thread_rng())
+            .cloned()
+            .ok_or_else(|| "没有可用的接口".to_string())
+    }
+
+    pub fn set_current(&mut self, spec: &MediaSpec, category_id: String) -> Result<(), String> {
+        if !self.category_exists(spec, &category_id) {
+            return Err("分类不存在".into());
+        }
+        self.current_category = category_id;
+        Ok(())
+    }
+
+    pub fn add_category(&mut self, spec: &MediaSpec, name: &str) -> Result<ApiCategory, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("分类名称不能为空".into());
+        }
+
+        let category = ApiCategory {
+            id: unique_id(spec.category_prefix, &mut self.taken_ids(spec)),
+            name: name.into(),
+            builtin: false,
+            endpoints: vec![],
+        };
+
+        self.custom_categories.push(category.clone());
+        Ok(category)
+    }
+
+    pub fn add_endpoint(
+        &mut self,
+        spec: &MediaSpec,
+        category_id: String,
+        name: &str,
+        url: &str,
+        extract: Option<ExtractRule>,
+        kind: ResponseKind,
+    ) -> Result<ApiEndpoint, String> {
+        let name = name.trim();
+        let url = url.trim();
+
+        if name.is_empty() || url.is_empty() {
+            return Err("名称和URL不能为空".into());
+        }
+
+        // 验证分类存在（不能是 random）
+        if category_id == RANDOM_CATEGORY || !self.category_exists(spec, &category_id) {
+            return Err("分类不存在".into());
+        }
+
+        let endpoint = ApiEndpoint {
+            id: unique_id(spec.endpoint_prefix, &mut self.taken_ids(spec)),
+            name: name.into(),
+            url: url.into(),
+            builtin: false,
+            extract: normalize_extract_rule(extract)?,
+            kind,
+        };
+
+        self.custom_endpoints.push(CustomEndpoint {
+            category_id,
+            endpoint: endpoint.clone(),
+        });
+        Ok(endpoint)
+    }
+
+    pub fn delete_endpoint(&mut self, spec: &MediaSpec, api_id: &str) -> Result<(), String> {
+        let len_before = self.custom_endpoints.len();
+        self.custom_endpoints.retain(|e| e.endpoint.id != api_id);
+
+        if self.custom_endpoints.len() == len_before {
+            return Err("未找到该接口".into());
+        }
+
+        // 检查当前分类是否还有接口，没有则回退到随机
+        if self.current_category != RANDOM_CATEGORY {
+            let has_endpoints = self
+                .categories(spec)
+                .iter()
+                .find(|c| c.id == self.current_category)
+                .map(|c| !c.endpoints.is_empty())
+                .unwrap_or(false);
+
+            if !has_endpoints {
+                self.current_category = RANDOM_CATEGORY.into();
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn delete_category(&mut self, spec: &MediaSpec, category_id: &str) -> Result<(), String> {
+        // 不能删除内置分类
+        if (spec.builtin_categories)()
+            .iter()
+            .any(|c| c.id == category_id)
+        {
+            return Err("无法删除内置分类".into());
+        }
+
+        let len_before = self.custom_categories.len();
+        self.custom_categories.retain(|c| c.id != category_id);
+
+        if self.custom_categories.len() == len_before {
+            return Err("未找到该分类".into());
+        }
+
+        // 删除该分类下的所有接口
+        self.custom_endpoints
+            .retain(|e| e.category_id != category_id);
+
+        // 如果当前选中的是被删除的分类，切换到随机
+        if self.current_category == category_id {
+            self.current_category = RANDOM_CATEGORY.into();
+        }
+
+        Ok(())
+    }
+
+    /// 内置与自定义的分类、接口已占用的 id
+    fn taken_ids(&self, spec: &MediaSpec) -> HashSet<String> {
+        (spec.builtin_categories)()
+            .iter()
+            .flat_map(|c| {
+                std::iter::once(c.id.clone()).chain(c.endpoints.iter().map(|e| e.id.clone()))
+            })
+            .chain(self.custom_categories.iter().map(|c| c.id.clone()))
+            .chain(self.custom_endpoints.iter().map(|e| e.endpoint.id.clone()))
+            .collect()
+    }
+
+    /// 当前分类指向已不存在的分类时回退到随机
+    pub fn normalize(&mut self, spec: &MediaSpec) {
+        if !self.category_exists(spec, &self.current_category) {
+            self.current_category = RANDOM_CATEGORY.into();
+        }
+    }
+}
+
+// ============================================================
+// 接口导入导出
+// ============================================================
+
+pub const CATALOG_BUNDLE_VERSION: u32 = 1;
+
+/// 可在机器间共享的接口清单，按扩展名以 JSON 或 TOML 读写
+#[derive(Serialize, Deserialize)]
+pub struct CatalogBundle {
+    pub version: u32,
+    /// 以媒体类型为键，如 `video`、`image`
+    #[serde(flatten)]
+    pub catalogs: BTreeMap<MediaKind, Vec<ApiCategory>>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportMode {
+    Merge,
+    Replace,
+}
+
+#[derive(Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportSummary {
+    pub categories_added: usize,
+    pub endpoints_added: usize,
+    pub duplicates_skipped: usize,
+    pub invalid_skipped: usize,
+}
+
+impl ImportSummary {
+    pub fn add(&mut self, other: ImportSummary) {
+        self.categories_added += other.categories_added;
+        self.endpoints_added += other.endpoints_added;
+        self.duplicates_skipped += other.duplicates_skipped;
+        self.invalid_skipped += other.invalid_skipped;
+    }
+}
+
+fn is_toml_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("toml"))
+        .unwrap_or(false)
+}
+
+/// 生成形如 `{prefix}{毫秒}` 且不与已有 id 冲突的新 id
+///
+/// 同一毫秒内或导入时连续分配会顺延到之后的毫秒，所以新增分类与接口也必须经过这里。
+fn unique_id(prefix: &str, taken: &mut HashSet<String>) -> String {
+    let mut n = now_millis();
+    loop {
+        let id = format!("{}{}", prefix, n);
+        if taken.insert(id.clone()) {
+            return id;
+        }
+        n += 1;
+    }
+}
+
+/// 只有 scheme 与主机名不区分大小写，路径与查询参数原样比较
+fn normalize_url(url: &str) -> String {
+    let url = url.trim();
+    let url = Url::parse(url).map_or_else(|_| url.to_string(), String::from);
+    url.trim_end_matches('/').to_string()
+}
+
+impl Catalog {
+    /// 导出自定义分类，以及挂在内置分类下的自定义接口（内置分类只带自定义接口）
+    pub fn export(&self, spec: &MediaSpec) -> Vec<ApiCategory> {
+        let mut exported = Vec::new();
+        for cat in (spec.builtin_categories)() {
+            let endpoints: Vec<ApiEndpoint> = self.endpoints_of(&cat.id).cloned().collect();
+            if !endpoints.is_empty() {
+                exported.push(ApiCategory { endpoints, ..cat });
+            }
+        }
+        for cat in &self.custom_categories {
+            exported.push(ApiCategory {
+                endpoints: self.endpoints_of(&cat.id).cloned().collect(),
+                ..cat.clone()
+            });
+        }
+        exported
+    }
+
+    /// 内置分类按 id 对应，自定义分类按名称对应，所有导入的分类与接口都重新分配 id，
+    /// URL 已存在的接口会被跳过
+    pub fn import(
+        &mut self,
+        spec: &MediaSpec,
+        incoming: Vec<ApiCategory>,
+        mode: ImportMode,
+    ) -> ImportSummary {
+        let mut summary = ImportSummary::default();
+        if let ImportMode::Replace = mode {
+            self.custom_categories.clear();
+            self.custom_endpoints.clear();
+        }
+
+        let builtin = (spec.builtin_categories)();
+        let mut taken_ids = self.taken_ids(spec);
+        let mut known_urls: HashSet<String> = builtin
+            .iter()
+            .flat_map(|c| c.endpoints.iter())
+            .chain(self.custom_endpoints.iter().map(|e| &e.endpoint))
+            .map(|e| normalize_url(&e.url))
+            .collect();
+
+        for cat in incoming {
+            let name = cat.name.trim().to_string();
+            let target_id = if let Some(b) = builtin.iter().find(|b| b.id == cat.id) {
+                b.id.clone()
+            } else if let Some(c) = self.custom_categories.iter().find(|c| c.name == name) {
+                c.id.clone()
+            } else if name.is_empty() {
+                summary.invalid_skipped += cat.endpoints.len();
+                continue;
+            } else {
+                let id = unique_id(spec.category_prefix, &mut taken_ids);
+                self.custom_categories.push(ApiCategory {
+                    id: id.clone(),
+                    name,
+                    builtin: false,
+                    endpoints: vec![],
+                });
+                summary.categories_added += 1;
+                id
+            };
+
+            for ep in cat.endpoints {
+                let ep_name = ep.name.trim();
+                let ep_url = ep.url.trim();
+                if ep_name.is_empty() || ep_url.is_empty() {
+                    summary.invalid_skipped += 1;
+                    continue;
+                }
+                let extract = match normalize_extract_rule(ep.extract) {
+                    Ok(extract) => extract,
+                    Err(_) => {
+                        summary.invalid_skipped += 1;
+                        continue;
+                    }
+                };
+                if !known_urls.insert(normalize_url(ep_url)) {
+                    summary.duplicates_skipped += 1;
+                    continue;
+                }
+
+                self.custom_endpoints.push(CustomEndpoint {
+                    category_id: target_id.clone(),
+                    endpoint: ApiEndpoint {
+                        id: unique_id(spec.endpoint_prefix, &mut taken_ids),
+                        name: ep_name.into(),
+                        url: ep_url.into(),
+                        builtin: false,
+                        extract,
+                        kind: ep.kind,
+                    },
+                });
+                summary.endpoints_added += 1;
+            }
+        }
+
+        // 替换模式下当前分类可能已被删除
+        self.normalize(spec);
+        summary
+    }
+}
+
+pub fn read_bundle(path: &Path) -> Result<CatalogBundle, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("读取失败: {}", e))?;
+
+    let bundle: CatalogBundle = if is_toml_path(path) {
+        toml::from_str(&text).map_err(|e| format!("解析失败: {}", e))?
+    } else {
+        serde_json::from_str(&text).map_err(|e| format!("解析失败: {}", e))?
+    };
+    if bundle.version > CATALOG_BUNDLE_VERSION {
+        return Err(format!("清单版本过新: {}", bundle.version));
+    }
+    Ok(bundle)
+}
+
+pub fn write_bundle(path: &Path, bundle: &CatalogBundle) -> Result<(), String> {
+    let text = if is_toml_path(path) {
+        toml::to_string_pretty(bundle).map_err(|e| format!("序列化失败: {}", e))?
+    } else {
+        serde_json::to_string_pretty(bundle).map_err(|e| format!("序列化失败: {}", e))?
+    };
+
+    fs::write(path, text).map_err(|e| format!("写入失败: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint(name: &str, url: &str) -> ApiEndpoint {
+        ApiEndpoint {
+            id: "ignored".into(),
+            name: name.into(),
+            url: url.into(),
+            builtin: false,
+            extract: None,
+            kind: ResponseKind::default(),
+        }
+    }
+
+    fn category(id: &str, name: &str, endpoints: Vec<ApiEndpoint>) -> ApiCategory {
+        ApiCategory {
+            id: id.into(),
+            name: name.into(),
+            builtin: false,
+            endpoints,
+        }
+    }
+
+    #[test]
+    fn normalize_url_ignores_host_case_space_and_trailing_slash() {
+        assert_eq!(
+            normalize_url("  HTTPS://A.example/Path/ "),
+            "https://a.example/Path"
+        );
+        assert_eq!(normalize_url("https://a.example"), "https://a.example");
+        assert_ne!(
+            normalize_url("https://a.example/v?id=A"),
+            normalize_url("https://a.example/v?id=a")
+        );
+    }
+
+    #[test]
+    fn import_skips_duplicates_and_invalid() {
+        let spec = MediaKind::Video.spec();
+        let builtin_url = (spec.builtin_categories)()[0].endpoints[0].url.clone();
+        let mut catalog = Catalog::default();
+        let summary = catalog.import(
+            spec,
+            vec![
+                category(
+                    "x",
+                    "新分类",
+                    vec![
+                        endpoint("a", "https://a.example/v"),
+                        endpoint("a2", "https://A.example/v/"),
+                        endpoint("b", &builtin_url),
+                        endpoint("", "https://c.example"),
+                    ],
+                ),
+                category("y", "  ", vec![endpoint("d", "https://d.example")]),
+            ],
+            ImportMode::Merge,
+        );
+        assert_eq!(summary.categories_added, 1);
+        assert_eq!(summary.endpoints_added, 1);
+        assert_eq!(summary.duplicates_skipped, 2);
+        assert_eq!(summary.invalid_skipped, 2);
+
+        // 再次导入同名分类时并入已有分类
+        let again = catalog.import(
+            spec,
+            vec![category(
+                "z",
+                "新分类",
+                vec![endpoint("e", "https://e.example")],
+            )],
+            ImportMode::Merge,
+        );
+        assert_eq!(again.categories_added, 0);
+        assert_eq!(catalog.custom_categories.len(), 1);
+        assert_eq!(catalog.custom_endpoints.len(), 2);
+    }
+
+    #[test]
+    fn ids_never_collide_after_import() {
+        let spec = MediaKind::Video.spec();
+        let mut catalog = Catalog::default();
+        let many: Vec<ApiEndpoint> = (0..50)
+            .map(|i| endpoint("e", &format!("https://{}.example", i)))
+            .collect();
+        catalog.import(spec, vec![category("x", "c", many)], ImportMode::Merge);
+        let category_id = catalog.custom_categories[0].id.clone();
+        let added = catalog
+            .add_endpoint(
+                spec,
+                category_id,
+                "new",
+                "https://new.example",
+                None,
+                ResponseKind::default(),
+            )
+            .unwrap();
+
+        let ids: HashSet<&str> = catalog
+            .custom_endpoints
+            .iter()
+            .map(|e| e.endpoint.id.as_str())
+            .collect();
+        assert_eq!(ids.len(), catalog.custom_endpoints.len());
+        catalog.delete_endpoint(spec, &added.id).unwrap();
+        assert_eq!(catalog.custom_endpoints.len(), 50);
+    }
+}
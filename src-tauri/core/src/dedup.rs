@@ -0,0 +1,75 @@
+//! 重复内容识别：按地址与文件开头的内容指纹判断最近是否已经出现过
+
+use std::collections::HashSet;
+
+/// 计算内容指纹所用的文件开头长度
+pub const FINGERPRINT_LEN: usize = 64 * 1024;
+
+/// 默认在最近多少条播放记录内不重复
+pub const DEFAULT_DEDUP_WINDOW: usize = 50;
+
+/// 解析到重复内容时最多重新解析的次数
+pub const MAX_DUPLICATE_RETRIES: usize = 3;
+
+/// 文件开头 [`FINGERPRINT_LEN`] 字节的 FNV-1a 哈希
+///
+/// 指纹会写入播放记录，所以不用 `DefaultHasher`：它的算法不保证在不同版本间一致。
+pub fn fingerprint(head: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &byte in &head[..head.len().min(FINGERPRINT_LEN)] {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// 已出现过的地址与内容指纹
+#[derive(Default)]
+pub struct SeenMedia {
+    urls: HashSet<String>,
+    fingerprints: HashSet<String>,
+}
+
+impl SeenMedia {
+    pub fn insert(&mut self, url: &str, fingerprint: Option<&str>) {
+        self.urls.insert(url.to_string());
+        if let Some(fingerprint) = fingerprint {
+            self.fingerprints.insert(fingerprint.to_string());
+        }
+    }
+
+    pub fn has_url(&self, url: &str) -> bool {
+        self.urls.contains(url)
+    }
+
+    pub fn has_fingerprint(&self, fingerprint: &str) -> bool {
+        self.fingerprints.contains(fingerprint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remembers_urls_and_fingerprints() {
+        let mut seen = SeenMedia::default();
+        seen.insert("https://a.example/1.mp4", Some("f1"));
+        seen.insert("https://a.example/2.mp4", None);
+
+        assert!(seen.has_url("https://a.example/1.mp4"));
+        assert!(seen.has_url("https://a.example/2.mp4"));
+        assert!(!seen.has_url("https://a.example/3.mp4"));
+        assert!(seen.has_fingerprint("f1"));
+        assert!(!seen.has_fingerprint("f2"));
+    }
+
+    #[test]
+    fn fingerprint_only_reads_head() {
+        let mut long = vec![7u8; FINGERPRINT_LEN];
+        let head = fingerprint(&long);
+        long.extend_from_slice(b"tail");
+        assert_eq!(fingerprint(&long), head);
+        assert_ne!(fingerprint(&long[..FINGERPRINT_LEN - 1]), head);
+    }
+}
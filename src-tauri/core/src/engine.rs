@@ -0,0 +1,936 @@
+use futures_util::future::join_all;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use uuid::Uuid;
+
+use reqwest::header::{CONTENT_TYPE, REFERER};
+
+use crate::cache::{
+    read_file_range, CachedFile, DiskCache, IMAGE_CACHE_BUDGET, PRELOAD_CACHE_BUDGET,
+};
+use crate::catalog::{
+    read_bundle, write_bundle, Catalog, CatalogBundle, ImportMode, ImportSummary,
+    CATALOG_BUNDLE_VERSION,
+};
+use crate::dedup::{fingerprint, SeenMedia, FINGERPRINT_LEN, MAX_DUPLICATE_RETRIES};
+use crate::favorites::{Favorite, FavoriteData, FavoriteSource, FavoritesLibrary, FAVORITES_DIR};
+use crate::history::{HistoryEntry, HistoryItem, PlaybackHistory, HISTORY_FILE};
+use crate::hls::{
+    self, is_playlist, HlsSession, HLS_CONTENT_TYPE, HLS_PRELOAD_SEGMENTS, SEGMENT_CONTENT_TYPE,
+};
+use crate::media::MediaKind;
+use crate::mime::{resolve_content_type, SNIFF_LEN};
+use crate::model::ApiEndpoint;
+use crate::phash::{cluster, hash_file, ImageHash, ImageHashIndex, IMAGE_HASH_FILE};
+use crate::preload::{
+    read_cached, DownloadProgress, ImagePreloadQueue, PoppedVideo, PreloadGuard, PreloadOutcome,
+    PreloadQueue, PreloadSettings, PreloadSlot, PreloadSource, PreloadedImage, PreloadedMedia,
+    PreloadedVideo, QueuedVideo, StreamReply,
+};
+use crate::proxy::fetch_window;
+use crate::remote::{RemoteImage, RemoteSessions, RemoteVideo};
+use crate::resolver::{build_client, download_media, open_media, resolve_endpoint, too_large};
+use crate::scheduler::PreloadScheduler;
+use crate::settings::{load_settings, write_settings_atomic, StoredSettings, SETTINGS_VERSION};
+
+/// 应用的全部运行时状态
+pub struct Engine {
+    pub preload: PreloadQueue,
+    /// 后台补充视频预加载队列，由桌面端在启动时运行
+    pub scheduler: PreloadScheduler,
+    /// 视频播放记录，保存在设置文件旁
+    pub history: PlaybackHistory,
+    /// 代理播放共用的客户端，复用连接
+    proxy_client: reqwest::Client,
+    /// 经 `stream:///remote/{id}` 代理播放的视频
+    pub remote: RemoteSessions<RemoteVideo>,
+    /// 经 `stream:///image/{id}` 提供的图片
+    pub images: RemoteSessions<RemoteImage>,
+    pub image_preload: ImagePreloadQueue,
+    image_cache: DiskCache,
+    /// 预加载图片的感知哈希，用于跳过相似的图片
+    image_hashes: ImageHashIndex,
+    /// 最近一次交给前端显示的图片
+    current_image: Mutex<Option<Arc<RemoteImage>>>,
+    pub favorites: FavoritesLibrary,
+    catalogs: HashMap<MediaKind, Mutex<Catalog>>,
+    preload_settings: Mutex<PreloadSettings>,
+    settings_path: PathBuf,
+    settings_lock: Mutex<()>,
+}
+
+impl Engine {
+    /// 从设置文件加载，文件不存在时使用默认目录；视频与图片分别缓存在 `cache_dir` 下的
+    /// `preload/` 与 `images/` 中，其中的旧文件会被清空
+    pub fn load(settings_path: PathBuf, cache_dir: PathBuf) -> Self {
+        let settings = load_settings(&settings_path);
+        let library_dir = settings
+            .library_dir
+            .clone()
+            .unwrap_or_else(|| settings_path.with_file_name(FAVORITES_DIR));
+        let engine = Self {
+            preload: PreloadQueue::new(DiskCache::open(
+                cache_dir.join("preload"),
+                PRELOAD_CACHE_BUDGET,
+            )),
+            scheduler: PreloadScheduler::default(),
+            history: PlaybackHistory::load(settings_path.with_file_name(HISTORY_FILE)),
+            proxy_client: build_client(),
+            remote: RemoteSessions::default(),
+            images: RemoteSessions::default(),
+            image_preload: ImagePreloadQueue::default(),
+            image_cache: DiskCache::open(cache_dir.join("images"), IMAGE_CACHE_BUDGET),
+            current_image: Mutex::new(None),
+            image_hashes: ImageHashIndex::load(settings_path.with_file_name(IMAGE_HASH_FILE)),
+            favorites: FavoritesLibrary::new(library_dir),
+            catalogs: settings
+                .catalogs
+                .into_iter()
+                .map(|(kind, catalog)| (kind, Mutex::new(catalog)))
+                .collect(),
+            preload_settings: Mutex::new(settings.preload.clone()),
+            settings_path,
+            settings_lock: Mutex::new(()),
+        };
+        engine.apply_preload_settings(&settings.preload);
+        engine.sync_preload_sources();
+        engine
+    }
+
+    /// 代理播放的请求，带上与下载相同的 Referer
+    pub fn proxy_request(&self, url: &str) -> reqwest::RequestBuilder {
+        let req = self.proxy_client.get(url);
+        match MediaKind::Video.spec().referer {
+            Some(referer) => req.header(REFERER, referer),
+            None => req,
+        }
+    }
+
+    pub fn catalog(&self, kind: MediaKind) -> MutexGuard<'_, Catalog> {
+        self.catalogs[&kind].lock().unwrap()
+    }
+
+    /// 修改分类目录并立即保存
+    pub fn update_catalog<R>(
+        &self,
+        kind: MediaKind,
+        f: impl FnOnce(&mut Catalog) -> Result<R, String>,
+    ) -> Result<R, String> {
+        let result = f(&mut self.catalog(kind))?;
+        if kind == MediaKind::Video {
+            self.sync_preload_sources();
+        }
+        self.save_settings()?;
+        Ok(result)
+    }
+
+    /// 分类或接口变化后丢弃不再属于当前分类的预加载视频，并让调度器按新分类补满
+    fn sync_preload_sources(&self) {
+        let endpoints = self
+            .catalog(MediaKind::Video)
+            .candidates(MediaKind::Video.spec())
+            .into_iter()
+            .map(|endpoint| endpoint.id)
+            .collect();
+        let removed = self.preload.retain_endpoints(endpoints);
+        if removed > 0 {
+            eprintln!("preload: 分类已切换，丢弃 {} 个预加载视频", removed);
+        }
+        self.scheduler.wake();
+    }
+
+    /// 调用方不能持有任何分类目录的锁
+    pub fn save_settings(&self) -> Result<(), String> {
+        let _guard = self.settings_lock.lock().unwrap();
+
+        let settings = StoredSettings {
+            version: SETTINGS_VERSION,
+            catalogs: MediaKind::ALL
+                .iter()
+                .map(|kind| (*kind, self.catalog(*kind).clone()))
+                .collect(),
+            preload: self.preload_settings(),
+            library_dir: Some(self.favorites.dir()),
+        };
+
+        write_settings_atomic(&self.settings_path, &settings)
+            .map_err(|e| format!("保存设置失败: {}", e))
+    }
+
+    fn pick_endpoint(&self, kind: MediaKind) -> Result<ApiEndpoint, String> {
+        self.catalog(kind).pick_endpoint(kind.spec())
+    }
+
+    /// 从当前分类中随机挑选接口并解析出媒体地址
+    pub async fn resolve_media_url(&self, kind: MediaKind) -> Result<String, String> {
+        let endpoint = self.pick_endpoint(kind)?;
+        resolve_endpoint(&endpoint, kind.spec()).await
+    }
+
+    /// 解析视频地址并登记为远程会话，供代理播放使用；同时记入播放记录
+    pub async fn fetch_video(&self) -> Result<Arc<RemoteVideo>, String> {
+        let endpoint = self.pick_endpoint(MediaKind::Video)?;
+        let url = resolve_endpoint(&endpoint, MediaKind::Video.spec()).await?;
+        let video = self.register_remote(url).await?;
+        self.history
+            .record(&video.url, &endpoint.id, &endpoint.name, None);
+        Ok(video)
+    }
+
+    /// 重新播放一条记录：预加载缓存中还有完整文件时返回可经 `stream:///video/{id}` 读取的 id，
+    /// 另外总会登记一个远程会话供回退
+    pub async fn play_history(
+        &self,
+        entry: HistoryEntry,
+    ) -> Result<(Option<String>, Arc<RemoteVideo>), String> {
+        let key = DiskCache::key_for(&entry.url);
+        let cached = match self.preload.cache().get(&key) {
+            Some(file) if !is_playlist(&entry.url, None) => {
+                let head = read_file_range(&file.path, 0, SNIFF_LEN as u64).unwrap_or_default();
+                let id = Uuid::new_v4().to_string();
+                self.preload.set_playing(PreloadedVideo {
+                    id: id.clone(),
+                    url: entry.url.clone(),
+                    source: PreloadSource {
+                        category: String::new(),
+                        endpoint: entry.endpoint,
+                        endpoint_name: entry.endpoint_name,
+                    },
+                    pinned: false,
+                    fingerprint: entry.fingerprint,
+                    media: PreloadedMedia::File {
+                        key,
+                        path: file.path,
+                        content_type: resolve_content_type(
+                            None,
+                            &head,
+                            MediaKind::Video.spec().default_content_type,
+                        ),
+                        progress: Arc::new(DownloadProgress::completed(file.len)),
+                    },
+                });
+                Some(id)
+            }
+            _ => None,
+        };
+        let remote = self.register_remote(entry.url).await?;
+        Ok((cached, remote))
+    }
+
+    /// 搜索播放记录，并标出预加载缓存中仍有的文件
+    pub fn search_history(&self, query: &str, limit: usize) -> Vec<HistoryItem> {
+        self.history
+            .search(query, limit)
+            .into_iter()
+            .map(|entry| HistoryItem {
+                cached_bytes: self.preload.cache().len_of(&DiskCache::key_for(&entry.url)),
+                entry,
+            })
+            .collect()
+    }
+
+    /// 登记远程视频；HLS 地址会同时建立播放会话
+    async fn register_remote(&self, url: String) -> Result<Arc<RemoteVideo>, String> {
+        let id = Uuid::new_v4().to_string();
+        let session = if is_playlist(&url, None) {
+            let resp = open_media(MediaKind::Video, &url).await?;
+            Some(Arc::new(hls::open_session(id.clone(), resp).await?))
+        } else {
+            None
+        };
+        Ok(self
+            .remote
+            .register(id.clone(), RemoteVideo::new(id, url, session)))
+    }
+
+    /// 解析图片并下载到缓存后登记，之后经 `stream:///image/{id}` 读取；
+    /// 与最近的图片相似时换一张重新解析，一直相似时仍显示最后一张
+    pub async fn fetch_image(&self) -> Result<Arc<RemoteImage>, String> {
+        let (PreloadedImage { image, .. }, _) = self.distinct_image().await?;
+        Ok(self.show_image(image))
+    }
+
+    async fn resolve_image(&self) -> Result<RemoteImage, String> {
+        let endpoint = self.pick_endpoint(MediaKind::Image)?;
+        let url = resolve_endpoint(&endpoint, MediaKind::Image.spec()).await?;
+        Ok(RemoteImage {
+            id: Uuid::new_v4().to_string(),
+            url,
+            endpoint: endpoint.id,
+            endpoint_name: endpoint.name,
+        })
+    }
+
+    /// 登记图片并记为当前显示的图片
+    fn show_image(&self, image: RemoteImage) -> Arc<RemoteImage> {
+        let image = self.images.register(image.id.clone(), image);
+        *self.current_image.lock().unwrap() = Some(image.clone());
+        image
+    }
+
+    /// 从磁盘缓存读取图片，未缓存或已被淘汰时重新下载
+    pub async fn read_image(
+        &self,
+        image: &RemoteImage,
+        range: Option<&str>,
+        if_range: Option<&str>,
+    ) -> Result<StreamReply, String> {
+        let file = self.cache_indexed_image(&image.url).await?;
+        let head = read_file_range(&file.path, 0, SNIFF_LEN as u64).unwrap_or_default();
+        let content_type =
+            resolve_content_type(None, &head, MediaKind::Image.spec().default_content_type);
+        read_cached(&file, &content_type, range, if_range)
+    }
+
+    /// 把图片下载到缓存，已缓存时直接返回
+    async fn cache_image(&self, url: &str) -> Result<CachedFile, String> {
+        let key = DiskCache::key_for(url);
+        if let Some(file) = self.image_cache.get(&key) {
+            return Ok(file);
+        }
+
+        let data = download_media(MediaKind::Image, url).await?;
+        let file = self.image_cache.insert(&key, &data)?;
+        let mut keep = self.image_preload.pinned_keys();
+        keep.insert(key);
+        self.image_cache.evict(&keep);
+        Ok(file)
+    }
+
+    /// 同 [`Self::cache_image`]，新下载的图片同时记入感知哈希索引；已缓存的图片下载时已经记过
+    async fn cache_indexed_image(&self, url: &str) -> Result<CachedFile, String> {
+        if let Some(file) = self.image_cache.get(&DiskCache::key_for(url)) {
+            return Ok(file);
+        }
+        let file = self.cache_image(url).await?;
+        self.index_image(url, file.path.clone()).await;
+        Ok(file)
+    }
+
+    /// 下载图片原始数据（经图片缓存），并记入感知哈希索引
+    pub async fn download_image(&self, url: &str) -> Result<Vec<u8>, String> {
+        let file = self.cache_indexed_image(url).await?;
+        fs::read(&file.path).map_err(|e| format!("读取缓存失败: {}", e))
+    }
+
+    /// 计算感知哈希并记入索引；与最近 `dedup_window` 加队列深度张图片相似时不记入，返回相似图片的地址
+    async fn index_image(&self, url: &str, path: PathBuf) -> Option<String> {
+        let settings = self.preload_settings();
+        match hash_file(path, settings.image_hash).await {
+            Ok(hash) => self.image_hashes.insert_unless_near(
+                url,
+                hash,
+                settings.dedup_window + settings.image_depth,
+                settings.image_distance,
+            ),
+            // 无法解码的格式（如 AVIF）不参与去重
+            Err(e) => {
+                eprintln!("phash: {}: {}", url, e);
+                None
+            }
+        }
+    }
+
+    /// 按并发数分批把图片预加载队列补满，返回当前队列长度；出错或期间被清空时停止
+    pub async fn preload_next_image(&self) -> usize {
+        let queue = &self.image_preload;
+        let generation = queue.generation();
+        loop {
+            let guards: Vec<_> = std::iter::from_fn(|| queue.try_begin()).collect();
+            if guards.is_empty() {
+                break;
+            }
+            let results = join_all(
+                guards
+                    .into_iter()
+                    .map(|guard| self.preload_one_image(guard, generation)),
+            )
+            .await;
+            if results.contains(&false) {
+                break;
+            }
+        }
+        queue.len()
+    }
+
+    /// 下载一张图片并入队，成功入队时返回 `true`
+    async fn preload_one_image(&self, _guard: PreloadGuard, generation: u64) -> bool {
+        match self.distinct_image().await {
+            Ok((image, None)) => self.image_preload.push(image, generation),
+            Ok((_, Some(_))) => {
+                eprintln!(
+                    "preload(图片): 连续 {} 次解析到相似的图片",
+                    MAX_DUPLICATE_RETRIES + 1
+                );
+                false
+            }
+            Err(e) => {
+                eprintln!("preload(图片): {}", e);
+                false
+            }
+        }
+    }
+
+    /// 解析并下载一张与最近的图片都不相似的图片，相似时换一张重新解析；
+    /// 连续相似时返回最后一张及与其相似的图片地址
+    async fn distinct_image(&self) -> Result<(PreloadedImage, Option<String>), String> {
+        let mut retries = 0;
+        loop {
+            let image = self.resolve_image().await?;
+            let file = self.cache_image(&image.url).await?;
+            let similar = self.index_image(&image.url, file.path).await;
+            let image = PreloadedImage {
+                image,
+                key: file.key,
+            };
+            match similar {
+                Some(similar) if retries < MAX_DUPLICATE_RETRIES => {
+                    eprintln!("phash: {} 与 {} 相似，跳过", image.image.url, similar);
+                    retries += 1;
+                }
+                similar => return Ok((image, similar)),
+            }
+        }
+    }
+
+    /// 取出下一张预加载的图片并登记到 `stream:///image/{id}`
+    pub fn pop_next_image(&self) -> Option<Arc<RemoteImage>> {
+        let PreloadedImage { image, .. } = self.image_preload.pop()?;
+        Some(self.show_image(image))
+    }
+
+    /// 按 id 查找 HLS 会话：远程会话或正在播放的预加载条目
+    pub fn hls_session(&self, id: &str) -> Option<Arc<HlsSession>> {
+        self.remote
+            .get(id)
+            .and_then(|video| video.hls.clone())
+            .or_else(|| self.preload.playing_hls(id))
+    }
+
+    /// 改写后的播放列表；直播列表每次都重新下载，失败时沿用上一次的内容
+    pub async fn hls_manifest(&self, session: &HlsSession) -> StreamReply {
+        if session.is_live() {
+            let refreshed = match hls::fetch_playlist(&session.playlist_url).await {
+                Ok((_, text)) => session.refresh(&text),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = refreshed {
+                eprintln!("hls: 刷新直播列表失败: {}", e);
+            }
+        }
+
+        let body = session.manifest().into_bytes();
+        StreamReply {
+            status: 200,
+            headers: vec![
+                ("Content-Type", HLS_CONTENT_TYPE.to_string()),
+                ("Cache-Control", "no-cache".to_string()),
+                ("Content-Length", body.len().to_string()),
+            ],
+            body,
+        }
+    }
+
+    /// HLS 分片、密钥等资源：已预加载的从缓存读取，否则按窗口代理上游
+    pub async fn hls_resource(
+        &self,
+        url: &str,
+        range: Option<&str>,
+        if_range: Option<&str>,
+    ) -> Result<StreamReply, String> {
+        // 缓存中的是已下载完成的小文件，读取不会等待
+        if let Some(file) = self.preload.cache().get(&DiskCache::key_for(url)) {
+            let head = read_file_range(&file.path, 0, SNIFF_LEN as u64).unwrap_or_default();
+            let content_type = resolve_content_type(None, &head, SEGMENT_CONTENT_TYPE);
+            return read_cached(&file, &content_type, range, if_range);
+        }
+
+        let resp = fetch_window(self.proxy_request(url), range, if_range).await?;
+        let from_start = match resp.content_range.as_deref() {
+            Some(range) => range.starts_with("bytes 0-"),
+            None => resp.status == 200,
+        };
+        let head = if from_start { &resp.data[..] } else { &[][..] };
+        let content_type = resolve_content_type(
+            resp.content_type.as_deref(),
+            &head[..head.len().min(SNIFF_LEN)],
+            SEGMENT_CONTENT_TYPE,
+        );
+        Ok(resp.into_reply(content_type))
+    }
+
+    /// 同一地址已在缓存中时直接复用；否则收到足以识别类型的文件头后即入队，下载继续进行
+    /// 解析到队列或最近播放记录中已有的视频时换一个重新解析
+    pub(crate) async fn preload_one(
+        &self,
+        slot: &mut PreloadSlot,
+    ) -> Result<PreloadOutcome, String> {
+        for _ in 0..=MAX_DUPLICATE_RETRIES {
+            let endpoint = self.pick_endpoint(MediaKind::Video)?;
+            match self.preload_attempt(&endpoint, slot).await {
+                Ok(Some(outcome)) => return Ok(outcome),
+                Ok(None) => {}
+                // 下载期间切换了分类，旧接口的错误不再有意义
+                Err(_) if !self.preload.accepts(&endpoint.id) => {
+                    return Ok(PreloadOutcome::Cancelled)
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(format!(
+            "连续 {} 次解析到重复的视频",
+            MAX_DUPLICATE_RETRIES + 1
+        ))
+    }
+
+    /// 队列与最近 `dedup_window` 条播放记录中的地址与指纹
+    fn seen_videos(&self) -> SeenMedia {
+        let mut seen = SeenMedia::default();
+        self.preload.note_queued(&mut seen);
+        self.history
+            .note_recent(self.preload_settings().dedup_window, &mut seen);
+        seen
+    }
+
+    /// 预加载一个视频，解析到重复的视频时返回 `Ok(None)`
+    /// 入队后被移除或清空时返回 [`PreloadOutcome::Cancelled`]
+    async fn preload_attempt(
+        &self,
+        endpoint: &ApiEndpoint,
+        slot: &mut PreloadSlot,
+    ) -> Result<Option<PreloadOutcome>, String> {
+        let spec = MediaKind::Video.spec();
+        let source = PreloadSource {
+            category: self.catalog(MediaKind::Video).current_category.clone(),
+            endpoint: endpoint.id.clone(),
+            endpoint_name: endpoint.name.clone(),
+        };
+        let url = resolve_endpoint(endpoint, spec).await?;
+        if self.seen_videos().has_url(&url) {
+            eprintln!("preload: 跳过重复的地址 {}", url);
+            return Ok(None);
+        }
+        if is_playlist(&url, None) {
+            let resp = open_media(MediaKind::Video, &url).await?;
+            return self
+                .preload_hls(url, source, resp, slot)
+                .await
+                .map(|queued| Some(PreloadOutcome::Queued(queued)));
+        }
+
+        let key = DiskCache::key_for(&url);
+
+        if let Some(file) = self.preload.cache().get(&key) {
+            let head = read_file_range(&file.path, 0, FINGERPRINT_LEN as u64).unwrap_or_default();
+            let fingerprint = fingerprint(&head);
+            if self.seen_videos().has_fingerprint(&fingerprint) {
+                eprintln!("preload: 跳过重复的内容 {}", url);
+                return Ok(None);
+            }
+            let queued = QueuedVideo {
+                id: Uuid::new_v4().to_string(),
+                url,
+            };
+            let video = PreloadedVideo {
+                id: queued.id.clone(),
+                url: queued.url.clone(),
+                source,
+                pinned: false,
+                fingerprint: Some(fingerprint),
+                media: PreloadedMedia::File {
+                    key,
+                    path: file.path,
+                    content_type: resolve_content_type(
+                        None,
+                        &head[..head.len().min(SNIFF_LEN)],
+                        spec.default_content_type,
+                    ),
+                    progress: Arc::new(DownloadProgress::completed(file.len)),
+                },
+            };
+            self.preload.push(video, slot)?;
+            return Ok(Some(PreloadOutcome::Queued(queued)));
+        }
+
+        let mut resp = open_media(MediaKind::Video, &url).await?;
+        let declared = resp
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string());
+        if is_playlist(resp.url().as_str(), declared.as_deref()) {
+            return self
+                .preload_hls(url, source, resp, slot)
+                .await
+                .map(|queued| Some(PreloadOutcome::Queued(queued)));
+        }
+
+        let progress = Arc::new(DownloadProgress::new(resp.content_length()));
+        let Some(mut writer) = self.preload.cache().create(&key)? else {
+            eprintln!("preload: 跳过正在下载的 {}", url);
+            return Ok(None);
+        };
+        let id = Uuid::new_v4().to_string();
+        let mut head = Vec::with_capacity(FINGERPRINT_LEN);
+        let mut queued = false;
+
+        let result = loop {
+            if progress.is_cancelled() {
+                break Err("预加载已取消".to_string());
+            }
+            let (chunk, eof) = match resp.chunk().await {
+                Ok(Some(chunk)) => (chunk, false),
+                Ok(None) => (Default::default(), true),
+                Err(e) => break Err(format!("读取{}失败: {}", spec.label, e)),
+            };
+            if let Err(e) = writer.write(&chunk) {
+                break Err(e);
+            }
+            if writer.len() > spec.max_bytes {
+                break Err(too_large(spec));
+            }
+            progress.advance(writer.len());
+
+            let room = FINGERPRINT_LEN - head.len();
+            head.extend_from_slice(&chunk[..chunk.len().min(room)]);
+
+            // 攒够识别类型与计算指纹所需的文件头后再入队
+            if !queued && (head.len() >= FINGERPRINT_LEN || eof) {
+                let content_type = resolve_content_type(
+                    declared.as_deref(),
+                    &head[..head.len().min(SNIFF_LEN)],
+                    spec.default_content_type,
+                );
+                // 地址与响应头都没表明是播放列表，只能靠文件头识别
+                if content_type == HLS_CONTENT_TYPE {
+                    self.preload.cache().abort(writer);
+                    let resp = open_media(MediaKind::Video, &url).await?;
+                    return self
+                        .preload_hls(url, source, resp, slot)
+                        .await
+                        .map(|queued| Some(PreloadOutcome::Queued(queued)));
+                }
+                // 下载期间其他预加载可能已入队，此时再取一次
+                let fingerprint = fingerprint(&head);
+                if self.seen_videos().has_fingerprint(&fingerprint) {
+                    self.preload.cache().abort(writer);
+                    eprintln!("preload: 跳过重复的内容 {}", url);
+                    return Ok(None);
+                }
+                let video = PreloadedVideo {
+                    id: id.clone(),
+                    url: url.clone(),
+                    source: source.clone(),
+                    pinned: false,
+                    fingerprint: Some(fingerprint),
+                    media: PreloadedMedia::File {
+                        key: key.clone(),
+                        path: writer.path().to_path_buf(),
+                        content_type,
+                        progress: progress.clone(),
+                    },
+                };
+                if let Err(e) = self.preload.push(video, slot) {
+                    break Err(e);
+                }
+                queued = true;
+            }
+            if eof {
+                break Ok(());
+            }
+        };
+
+        match result {
+            Ok(()) => {
+                let file = self.preload.cache().commit(writer)?;
+                progress.finish(Ok(file.len));
+                self.preload.evict();
+                Ok(Some(PreloadOutcome::Queued(QueuedVideo { id, url })))
+            }
+            // 条目被移除或队列被清空，不算失败
+            Err(e) if progress.is_cancelled() => {
+                progress.finish(Err(e));
+                self.preload.cache().abort(writer);
+                Ok(Some(PreloadOutcome::Cancelled))
+            }
+            Err(e) => {
+                progress.finish(Err(e.clone()));
+                self.preload.remove(&id);
+                self.preload.cache().abort(writer);
+                Err(e)
+            }
+        }
+    }
+
+    /// HLS 不缓存播放列表本身，只提前下载开头几个分片，其余分片播放时再代理
+    async fn preload_hls(
+        &self,
+        url: String,
+        source: PreloadSource,
+        resp: reqwest::Response,
+        slot: &mut PreloadSlot,
+    ) -> Result<QueuedVideo, String> {
+        let id = Uuid::new_v4().to_string();
+        let session = hls::open_session(id.clone(), resp).await?;
+        for resource in session.leading_resources(HLS_PRELOAD_SEGMENTS) {
+            self.cache_resource(&resource).await?;
+        }
+        eprintln!("preload: hls {} 已缓存开头的分片", session.playlist_url);
+
+        let video = PreloadedVideo {
+            id: id.clone(),
+            url: url.clone(),
+            source,
+            pinned: false,
+            fingerprint: None,
+            media: PreloadedMedia::Hls(Arc::new(session)),
+        };
+        self.preload.push(video, slot)?;
+        self.preload.evict();
+        Ok(QueuedVideo { id, url })
+    }
+
+    /// 把单个资源完整下载到缓存，已缓存或正在下载时跳过
+    async fn cache_resource(&self, url: &str) -> Result<(), String> {
+        let cache = self.preload.cache();
+        let key = DiskCache::key_for(url);
+        if cache.get(&key).is_some() {
+            return Ok(());
+        }
+
+        let spec = MediaKind::Video.spec();
+        let mut resp = open_media(MediaKind::Video, url).await?;
+        let Some(mut writer) = cache.create(&key)? else {
+            return Ok(());
+        };
+        let result = loop {
+            match resp.chunk().await {
+                Ok(Some(chunk)) => {
+                    if let Err(e) = writer.write(&chunk) {
+                        break Err(e);
+                    }
+                    if writer.len() > spec.max_bytes {
+                        break Err(too_large(spec));
+                    }
+                }
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(format!("读取分片失败: {}", e)),
+            }
+        };
+
+        match result {
+            Ok(()) => cache.commit(writer).map(|_| ()),
+            Err(e) => {
+                cache.abort(writer);
+                Err(e)
+            }
+        }
+    }
+
+    /// 按空闲的下载名额并行补充一批预加载视频，没有空闲名额时返回空列表
+    pub async fn preload_round(&self) -> Vec<Result<PreloadOutcome, String>> {
+        let slots: Vec<_> = std::iter::from_fn(|| self.preload.try_begin()).collect();
+        join_all(
+            slots
+                .into_iter()
+                .map(|mut slot| async move { self.preload_one(&mut slot).await }),
+        )
+        .await
+    }
+
+    /// 补充一批预加载视频，等这一批下载结束后返回队列长度
+    pub async fn preload_next(&self) -> usize {
+        for result in self.preload_round().await {
+            if let Err(e) = result {
+                eprintln!("preload: {}", e);
+            }
+        }
+        self.preload.len()
+    }
+
+    pub fn preload_settings(&self) -> PreloadSettings {
+        self.preload_settings.lock().unwrap().clone()
+    }
+
+    /// 立即生效并保存
+    pub fn set_preload_settings(
+        &self,
+        mut settings: PreloadSettings,
+    ) -> Result<PreloadSettings, String> {
+        settings.normalize();
+        self.apply_preload_settings(&settings);
+        *self.preload_settings.lock().unwrap() = settings.clone();
+        self.scheduler.wake();
+        self.save_settings()?;
+        Ok(settings)
+    }
+
+    fn apply_preload_settings(&self, settings: &PreloadSettings) {
+        self.preload
+            .configure(settings.video_depth, settings.concurrency);
+        self.preload.cache().set_budget(settings.budget_bytes);
+        self.preload.evict();
+        self.image_preload
+            .configure(settings.image_depth, settings.concurrency);
+    }
+
+    /// 取出下一个预加载视频，返回其 id 与可供回退的远程会话
+    pub fn pop_next_video(&self) -> Option<(String, Arc<RemoteVideo>)> {
+        let PoppedVideo {
+            id,
+            url,
+            source,
+            fingerprint,
+            hls,
+        } = self.preload.pop_next()?;
+        self.scheduler.wake();
+        self.history.record(
+            &url,
+            &source.endpoint,
+            &source.endpoint_name,
+            fingerprint.as_deref(),
+        );
+        // HLS 条目本身就经本地代理播放，回退时沿用同一会话
+        let remote_id = hls
+            .as_ref()
+            .map_or_else(|| Uuid::new_v4().to_string(), |session| session.id.clone());
+        Some((
+            id,
+            self.remote
+                .register(remote_id.clone(), RemoteVideo::new(remote_id, url, hls)),
+        ))
+    }
+
+    /// 更换收藏库目录并保存设置
+    pub fn set_library_dir(&self, dir: PathBuf) -> Result<(), String> {
+        self.favorites.set_dir(dir)?;
+        self.save_settings()
+    }
+
+    /// 收藏当前播放的视频：预加载缓存中有完整文件时直接复制，否则重新下载
+    pub async fn save_current_video(&self) -> Result<Favorite, String> {
+        let entry = self.history.current().ok_or("当前没有播放的视频")?;
+        let source = FavoriteSource {
+            url: &entry.url,
+            endpoint: &entry.endpoint,
+            endpoint_name: &entry.endpoint_name,
+        };
+        let key = DiskCache::key_for(&entry.url);
+        if let Some(file) = self.preload.cache().get(&key) {
+            return self
+                .favorites
+                .save(MediaKind::Video, source, FavoriteData::File(&file.path));
+        }
+        let data = download_media(MediaKind::Video, &entry.url).await?;
+        self.favorites
+            .save(MediaKind::Video, source, FavoriteData::Bytes(&data))
+    }
+
+    /// 收藏当前显示的图片，图片已在缓存中时直接复制
+    pub async fn save_current_image(&self) -> Result<Favorite, String> {
+        let image = self
+            .current_image
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or("当前没有显示的图片")?;
+        let file = self.cache_image(&image.url).await?;
+        let favorite = self.favorites.save(
+            MediaKind::Image,
+            FavoriteSource {
+                url: &image.url,
+                endpoint: &image.endpoint,
+                endpoint_name: &image.endpoint_name,
+            },
+            FavoriteData::File(&file.path),
+        )?;
+        Ok(self.hash_favorite(favorite).await)
+    }
+
+    /// 按当前设置的算法补算收藏图片的感知哈希并写回记录，失败时原样返回
+    async fn hash_favorite(&self, mut favorite: Favorite) -> Favorite {
+        let algorithm = self.preload_settings().image_hash;
+        if favorite.image_hash.map(|h| h.algorithm) == Some(algorithm) {
+            return favorite;
+        }
+        let hashed = match self.favorites.file_path(&favorite) {
+            Ok(path) => hash_file(path, algorithm)
+                .await
+                .and_then(|hash| self.favorites.set_image_hash(&favorite.id, hash)),
+            Err(e) => Err(e),
+        };
+        match hashed {
+            Ok(updated) => favorite = updated,
+            Err(e) => eprintln!("favorites: {} 计算感知哈希失败: {}", favorite.id, e),
+        }
+        favorite
+    }
+
+    /// 收藏中互相相似的图片分组，组内最新的在前，成员多的组在前
+    pub async fn similar_favorites(&self) -> Vec<Vec<Favorite>> {
+        let settings = self.preload_settings();
+        let mut favorites = Vec::new();
+        for favorite in self.favorites.list(Some(MediaKind::Image), None) {
+            let favorite = self.hash_favorite(favorite).await;
+            if favorite.image_hash.is_some() {
+                favorites.push(favorite);
+            }
+        }
+
+        let hashes: Vec<ImageHash> = favorites.iter().filter_map(|f| f.image_hash).collect();
+        let mut groups: Vec<Vec<Favorite>> = cluster(&hashes, settings.image_distance)
+            .into_iter()
+            .map(|group| group.into_iter().map(|i| favorites[i].clone()).collect())
+            .collect();
+        groups.sort_by_key(|group| Reverse(group.len()));
+        groups
+    }
+
+    /// 按 `Range` / `If-Range` 读取收藏的文件，供 `stream:///favorite/{id}` 使用
+    pub fn read_favorite(
+        &self,
+        id: &str,
+        range: Option<&str>,
+        if_range: Option<&str>,
+    ) -> Option<Result<StreamReply, String>> {
+        let favorite = self.favorites.get(id)?;
+        let path = match self.favorites.file_path(&favorite) {
+            Ok(path) => path,
+            Err(e) => return Some(Err(e)),
+        };
+        let file = CachedFile {
+            key: favorite.id.clone(),
+            path,
+            len: favorite.size,
+        };
+        Some(read_cached(&file, &favorite.content_type, range, if_range))
+    }
+
+    pub fn export_catalog(&self, path: &Path) -> Result<(), String> {
+        let bundle = CatalogBundle {
+            version: CATALOG_BUNDLE_VERSION,
+            catalogs: MediaKind::ALL
+                .iter()
+                .map(|kind| (*kind, self.catalog(*kind).export(kind.spec())))
+                .collect(),
+        };
+        write_bundle(path, &bundle)
+    }
+
+    pub fn import_catalog(&self, path: &Path, mode: ImportMode) -> Result<ImportSummary, String> {
+        let mut bundle = read_bundle(path)?;
+
+        let mut summary = ImportSummary::default();
+        for kind in MediaKind::ALL {
+            let incoming = bundle.catalogs.remove(&kind).unwrap_or_default();
+            summary.add(self.catalog(kind).import(kind.spec(), incoming, mode));
+        }
+        self.sync_preload_sources();
+
+        self.save_settings()?;
+        Ok(summary)
+    }
+}
@@ -0,0 +1,265 @@
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::media::MediaSpec;
+use crate::model::{ApiEndpoint, ExtractRule};
+use crate::resolver::resolve_redirect_location;
+
+#[derive(Deserialize)]
+struct ApiResponse {
+    code: i32,
+    #[allow(dead_code)]
+    msg: Option<String>,
+    data: String,
+}
+
+pub fn extract_image_url_from_json(value: &Value) -> Option<String> {
+    if let Value::String(url) = value {
+        return Some(url.clone());
+    }
+
+    let direct_keys = ["data", "imgurl", "url", "image", "pic"];
+    for key in direct_keys {
+        if let Some(url) = value.get(key).and_then(|v| v.as_str()) {
+            return Some(url.to_string());
+        }
+    }
+
+    if let Some(data) = value.get("data") {
+        for key in ["url", "imgurl", "image", "pic"] {
+            if let Some(url) = data.get(key).and_then(|v| v.as_str()) {
+                return Some(url.to_string());
+            }
+        }
+    }
+
+    None
+}
+
+/// 支持 JSON Pointer（以 `/` 开头）或 `$.data.list[0].url` 形式的点路径
+fn select_json<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    let path = path.trim();
+    if path.starts_with('/') {
+        return value.pointer(path);
+    }
+
+    let path = path.strip_prefix('$').unwrap_or(path);
+    let mut current = value;
+    for segment in path.split('.').filter(|s| !s.is_empty()) {
+        let (key, indices) = match segment.find('[') {
+            Some(i) => (&segment[..i], &segment[i..]),
+            None => (segment, ""),
+        };
+        if !key.is_empty() {
+            current = current.get(key)?;
+        }
+        for index in indices.split('[').filter(|s| !s.is_empty()) {
+            let index = index.strip_suffix(']')?.trim().parse::<usize>().ok()?;
+            current = current.get(index)?;
+        }
+    }
+    Some(current)
+}
+
+fn json_to_url(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) if !s.trim().is_empty() => Some(s.trim().to_string()),
+        Value::Array(items) => {
+            let urls: Vec<String> = items.iter().filter_map(json_to_url).collect();
+            urls.choose(&mut rand::thread_rng()).cloned()
+        }
+        _ => None,
+    }
+}
+
+/// 宽松比较：`"200"` 与 `200` 视为相等
+fn loose_eq(actual: &Value, expected: &Value) -> bool {
+    if actual == expected {
+        return true;
+    }
+    let as_text = |v: &Value| match v {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    as_text(actual) == as_text(expected)
+}
+
+/// 返回 `Ok(None)` 表示规则未命中，交给内置的启发式解析
+pub fn apply_extract_rule(rule: &ExtractRule, text: &str) -> Result<Option<String>, String> {
+    let trimmed = text.trim();
+
+    if let Ok(json) = serde_json::from_str::<Value>(trimmed) {
+        if let Some(success) = &rule.success {
+            let actual = select_json(&json, &success.path);
+            if !actual
+                .map(|v| loose_eq(v, &success.equals))
+                .unwrap_or(false)
+            {
+                let actual = actual
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|| "null".into());
+                return Err(format!("API返回错误: {} = {}", success.path, actual));
+            }
+        }
+        if let Some(path) = &rule.path {
+            if let Some(url) = select_json(&json, path).and_then(json_to_url) {
+                return Ok(Some(url));
+            }
+        }
+    }
+
+    if let Some(pattern) = &rule.regex {
+        let re = regex::Regex::new(pattern).map_err(|e| format!("正则无效: {}", e))?;
+        if let Some(caps) = re.captures(trimmed) {
+            if let Some(m) = caps.get(1).or_else(|| caps.get(0)) {
+                return Ok(Some(m.as_str().trim().to_string()));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// 按接口配置的规则提取地址，相对地址基于响应的最终 URL 补全
+pub fn extract_with_rule(
+    endpoint: &ApiEndpoint,
+    base_url: &reqwest::Url,
+    text: &str,
+) -> Result<Option<String>, String> {
+    let rule = match &endpoint.extract {
+        Some(rule) => rule,
+        None => return Ok(None),
+    };
+    Ok(apply_extract_rule(rule, text)?.and_then(|url| resolve_redirect_location(base_url, &url)))
+}
+
+/// 去掉空字段并校验正则，全部为空时视为未配置
+pub fn normalize_extract_rule(rule: Option<ExtractRule>) -> Result<Option<ExtractRule>, String> {
+    let rule = match rule {
+        Some(rule) => rule,
+        None => return Ok(None),
+    };
+    let clean = |s: Option<String>| s.map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+
+    let rule = ExtractRule {
+        path: clean(rule.path),
+        success: rule.success.filter(|s| !s.path.trim().is_empty()),
+        regex: clean(rule.regex),
+    };
+    if let Some(pattern) = &rule.regex {
+        regex::Regex::new(pattern).map_err(|e| format!("正则无效: {}", e))?;
+    }
+
+    if rule.path.is_none() && rule.success.is_none() && rule.regex.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(rule))
+}
+
+pub fn extract_video_url_from_json(value: &Value) -> Result<String, String> {
+    let api_resp: ApiResponse =
+        serde_json::from_value(value.clone()).map_err(|e| format!("解析失败: {}", e))?;
+    if api_resp.code != 200 {
+        return Err("API返回错误".into());
+    }
+    Ok(api_resp.data)
+}
+
+pub fn find_media_in_html(html: &str, spec: &MediaSpec) -> Option<String> {
+    let capture = |pattern: &str| {
+        regex::Regex::new(pattern)
+            .ok()
+            .and_then(|re| re.captures(html))
+            .and_then(|caps| caps.get(1))
+            .map(|m| m.as_str().trim().to_string())
+    };
+
+    for prop in spec.og_properties {
+        let prop = regex::escape(prop);
+        let patterns = [
+            format!(
+                r#"(?i)<meta[^>]+(?:property|name)\s*=\s*["']{}["'][^>]*\bcontent\s*=\s*["']([^"']+)["']"#,
+                prop
+            ),
+            format!(
+                r#"(?i)<meta[^>]+\bcontent\s*=\s*["']([^"']+)["'][^>]*(?:property|name)\s*=\s*["']{}["']"#,
+                prop
+            ),
+        ];
+        if let Some(url) = patterns.iter().find_map(|p| capture(p)) {
+            return Some(url);
+        }
+    }
+
+    spec.html_tags.iter().find_map(|tag| {
+        capture(&format!(
+            r#"(?i)<{}\b[^>]*\bsrc\s*=\s*["']([^"']+)["']"#,
+            tag
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::SuccessRule;
+
+    fn rule(
+        path: Option<&str>,
+        success: Option<(&str, Value)>,
+        regex: Option<&str>,
+    ) -> ExtractRule {
+        ExtractRule {
+            path: path.map(str::to_string),
+            success: success.map(|(path, equals)| SuccessRule {
+                path: path.to_string(),
+                equals,
+            }),
+            regex: regex.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn dotted_path_and_pointer() {
+        let body = r#"{"code":"200","data":{"list":[{"url":" https://a/1.mp4 "}]}}"#;
+        let dotted = rule(Some("$.data.list[0].url"), None, None);
+        assert_eq!(
+            apply_extract_rule(&dotted, body).unwrap().as_deref(),
+            Some("https://a/1.mp4")
+        );
+        let pointer = rule(Some("/data/list/0/url"), None, None);
+        assert_eq!(
+            apply_extract_rule(&pointer, body).unwrap().as_deref(),
+            Some("https://a/1.mp4")
+        );
+        let missing = rule(Some("data.list[3].url"), None, None);
+        assert_eq!(apply_extract_rule(&missing, body).unwrap(), None);
+    }
+
+    #[test]
+    fn success_condition_is_loose() {
+        let body = r#"{"code":"200","url":"https://a/1.jpg"}"#;
+        let ok = rule(Some("url"), Some(("code", Value::from(200))), None);
+        assert!(apply_extract_rule(&ok, body).unwrap().is_some());
+        let failed = rule(Some("url"), Some(("code", Value::from(0))), None);
+        assert!(apply_extract_rule(&failed, body).is_err());
+    }
+
+    #[test]
+    fn regex_prefers_first_group() {
+        let text = "video: <https://a/2.mp4>";
+        let grouped = rule(None, None, Some(r"<(https?://[^>]+)>"));
+        assert_eq!(
+            apply_extract_rule(&grouped, text).unwrap().as_deref(),
+            Some("https://a/2.mp4")
+        );
+    }
+
+    #[test]
+    fn normalize_drops_empty_and_rejects_bad_regex() {
+        let empty = rule(Some("  "), Some(("  ", Value::Null)), Some(""));
+        assert!(normalize_extract_rule(Some(empty)).unwrap().is_none());
+        assert!(normalize_extract_rule(Some(rule(None, None, Some("(")))).is_err());
+    }
+}
@@ -0,0 +1,300 @@
+//! 收藏库：把视频、图片保存到用户选择的目录，每个文件旁有一个记录来源的 JSON 文件
+
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+use crate::cache::read_file_range;
+use crate::media::MediaKind;
+use crate::mime::{extension_for, resolve_content_type, SNIFF_LEN};
+use crate::model::now_millis;
+use crate::phash::ImageHash;
+use crate::settings::write_json_atomic;
+
+/// 默认的收藏目录名，位于设置文件旁
+pub const FAVORITES_DIR: &str = "favorites";
+
+/// 一个收藏，与同名的 `{id}.json` 一起保存在库目录中
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Favorite {
+    pub id: String,
+    pub media: MediaKind,
+    /// 库目录中的媒体文件名
+    pub file: String,
+    pub content_type: String,
+    pub size: u64,
+    /// 原始地址
+    pub url: String,
+    pub endpoint: String,
+    pub endpoint_name: String,
+    /// 收藏时间，Unix 毫秒
+    pub saved_at: u64,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// 图片的感知哈希，列出相似图片时按需补算
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_hash: Option<ImageHash>,
+}
+
+/// 收藏的来源
+pub struct FavoriteSource<'a> {
+    pub url: &'a str,
+    pub endpoint: &'a str,
+    pub endpoint_name: &'a str,
+}
+
+/// 要保存的内容：已在缓存中的文件直接复制，否则写入下载到的数据
+pub enum FavoriteData<'a> {
+    File(&'a Path),
+    Bytes(&'a [u8]),
+}
+
+pub struct FavoritesLibrary {
+    dir: Mutex<PathBuf>,
+}
+
+/// id 只由字母、数字与 `-` 组成，避免拼出库目录以外的路径
+fn valid_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// 记录文件可能被手动修改：媒体文件名必须是 `{id}.{ext}` 且只有一级，不能指向库目录以外
+fn valid_file(favorite: &Favorite) -> bool {
+    let ext = match favorite.file.strip_prefix(&format!("{}.", favorite.id)) {
+        Some(ext) => ext,
+        None => return false,
+    };
+    let mut components = Path::new(&favorite.file).components();
+    !ext.is_empty()
+        && ext.chars().all(|c| c.is_ascii_alphanumeric())
+        && matches!(components.next(), Some(Component::Normal(_)))
+        && components.next().is_none()
+}
+
+/// 读取记录文件，id 与文件名不符或媒体文件名无效时忽略
+fn read_sidecar(path: &Path) -> Option<Favorite> {
+    let text = fs::read_to_string(path).ok()?;
+    let favorite: Favorite = serde_json::from_str(&text).ok()?;
+    let valid = valid_id(&favorite.id)
+        && path.file_stem()? == favorite.id.as_str()
+        && valid_file(&favorite);
+    valid.then_some(favorite)
+}
+
+/// 去掉空白与重复的标签，保持原有顺序
+fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut result: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim();
+        if !tag.is_empty() && !result.iter().any(|t| t == tag) {
+            result.push(tag.to_string());
+        }
+    }
+    result
+}
+
+impl FavoritesLibrary {
+    /// 目录在第一次保存时才创建
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir: Mutex::new(dir),
+        }
+    }
+
+    pub fn dir(&self) -> PathBuf {
+        self.dir.lock().unwrap().clone()
+    }
+
+    /// 更换库目录，已有的收藏不会移动
+    pub fn set_dir(&self, dir: PathBuf) -> Result<(), String> {
+        fs::create_dir_all(&dir).map_err(|e| format!("创建收藏目录失败: {}", e))?;
+        *self.dir.lock().unwrap() = dir;
+        Ok(())
+    }
+
+    fn sidecar_path(dir: &Path, id: &str) -> PathBuf {
+        dir.join(format!("{}.json", id))
+    }
+
+    /// 收藏的媒体文件路径，拼接后仍须位于库目录中
+    pub fn file_path(&self, favorite: &Favorite) -> Result<PathBuf, String> {
+        let dir = self.dir();
+        let path = dir.join(&favorite.file);
+        if !valid_file(favorite) || !path.starts_with(&dir) {
+            return Err("收藏文件路径无效".into());
+        }
+        Ok(path)
+    }
+
+    pub fn save(
+        &self,
+        media: MediaKind,
+        source: FavoriteSource<'_>,
+        data: FavoriteData<'_>,
+    ) -> Result<Favorite, String> {
+        let dir = self.dir();
+        fs::create_dir_all(&dir).map_err(|e| format!("创建收藏目录失败: {}", e))?;
+
+        let head = match data {
+            FavoriteData::File(path) => read_file_range(path, 0, SNIFF_LEN as u64)
+                .map_err(|e| format!("读取缓存失败: {}", e))?,
+            FavoriteData::Bytes(bytes) => bytes[..bytes.len().min(SNIFF_LEN)].to_vec(),
+        };
+        let spec = media.spec();
+        let content_type = resolve_content_type(None, &head, spec.default_content_type);
+        let ext = extension_for(&content_type).unwrap_or("bin");
+
+        let id = Uuid::new_v4().to_string();
+        let file = format!("{}.{}", id, ext);
+        let path = dir.join(&file);
+        let size = match data {
+            FavoriteData::File(src) => fs::copy(src, &path),
+            FavoriteData::Bytes(bytes) => fs::write(&path, bytes).map(|_| bytes.len() as u64),
+        }
+        .map_err(|e| format!("保存{}失败: {}", spec.label, e))?;
+
+        let favorite = Favorite {
+            id,
+            media,
+            file,
+            content_type,
+            size,
+            url: source.url.to_string(),
+            endpoint: source.endpoint.to_string(),
+            endpoint_name: source.endpoint_name.to_string(),
+            saved_at: now_millis() as u64,
+            tags: Vec::new(),
+            image_hash: None,
+        };
+        if let Err(e) = write_json_atomic(&Self::sidecar_path(&dir, &favorite.id), &favorite) {
+            let _ = fs::remove_file(&path);
+            return Err(e);
+        }
+        Ok(favorite)
+    }
+
+    pub fn get(&self, id: &str) -> Option<Favorite> {
+        if !valid_id(id) {
+            return None;
+        }
+        read_sidecar(&Self::sidecar_path(&self.dir(), id))
+    }
+
+    /// 库目录中的收藏，最新的在前；`media`、`tag` 为 `None` 时不筛选
+    pub fn list(&self, media: Option<MediaKind>, tag: Option<&str>) -> Vec<Favorite> {
+        let entries = match fs::read_dir(self.dir()) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+        let mut favorites: Vec<Favorite> = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != "json" {
+                    return None;
+                }
+                read_sidecar(&path)
+            })
+            .filter(|f| media.is_none_or(|m| f.media == m))
+            .filter(|f| tag.is_none_or(|t| f.tags.iter().any(|tag| tag == t)))
+            .collect();
+        favorites.sort_by_key(|f| Reverse(f.saved_at));
+        favorites
+    }
+
+    pub fn set_tags(&self, id: &str, tags: Vec<String>) -> Result<Favorite, String> {
+        let mut favorite = self.get(id).ok_or("收藏不存在")?;
+        favorite.tags = normalize_tags(tags);
+        write_json_atomic(&Self::sidecar_path(&self.dir(), id), &favorite)?;
+        Ok(favorite)
+    }
+
+    pub fn set_image_hash(&self, id: &str, hash: ImageHash) -> Result<Favorite, String> {
+        let mut favorite = self.get(id).ok_or("收藏不存在")?;
+        favorite.image_hash = Some(hash);
+        write_json_atomic(&Self::sidecar_path(&self.dir(), id), &favorite)?;
+        Ok(favorite)
+    }
+
+    /// 删除媒体文件与记录文件
+    pub fn remove(&self, id: &str) -> Result<(), String> {
+        let favorite = self.get(id).ok_or("收藏不存在")?;
+        match fs::remove_file(self.file_path(&favorite)?) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(format!("删除收藏失败: {}", e));
+            }
+            _ => {}
+        }
+        fs::remove_file(Self::sidecar_path(&self.dir(), id))
+            .map_err(|e| format!("删除收藏失败: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    fn library() -> FavoritesLibrary {
+        let dir = std::env::temp_dir().join(format!("favorites-test-{}", Uuid::new_v4()));
+        FavoritesLibrary::new(dir)
+    }
+
+    fn save(library: &FavoritesLibrary) -> Favorite {
+        let source = FavoriteSource {
+            url: "https://example.com/a.png",
+            endpoint: "api",
+            endpoint_name: "接口",
+        };
+        library
+            .save(MediaKind::Image, source, FavoriteData::Bytes(PNG))
+            .unwrap()
+    }
+
+    #[test]
+    fn saves_and_removes_inside_library() {
+        let library = library();
+        let favorite = save(&library);
+        assert_eq!(favorite.file, format!("{}.png", favorite.id));
+        assert!(library.file_path(&favorite).unwrap().exists());
+        assert_eq!(library.list(None, None).len(), 1);
+
+        library.remove(&favorite.id).unwrap();
+        assert!(library.list(None, None).is_empty());
+        let _ = fs::remove_dir_all(library.dir());
+    }
+
+    #[test]
+    fn ignores_tampered_sidecars() {
+        let library = library();
+        let dir = library.dir();
+        let victim = dir.with_extension("victim");
+        fs::write(&victim, b"keep").unwrap();
+
+        for file in [
+            format!("../{}", victim.file_name().unwrap().to_string_lossy()),
+            victim.display().to_string(),
+            "other.png".to_string(),
+        ] {
+            let mut favorite = save(&library);
+            favorite.file = file;
+            write_json_atomic(
+                &FavoritesLibrary::sidecar_path(&dir, &favorite.id),
+                &favorite,
+            )
+            .unwrap();
+            assert!(library.get(&favorite.id).is_none());
+            assert!(library.remove(&favorite.id).is_err());
+            assert!(library.file_path(&favorite).is_err());
+        }
+        assert!(library.list(None, None).is_empty());
+        assert!(victim.exists());
+
+        let _ = fs::remove_file(victim);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,250 @@
+//! 视频播放记录：有上限的列表，保存在设置文件旁，支持后退/前进与按关键字搜索
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+use crate::dedup::SeenMedia;
+use crate::model::now_millis;
+use crate::settings::{load_versioned_json, write_json_atomic};
+
+pub const HISTORY_FILE: &str = "history.json";
+const HISTORY_VERSION: u32 = 1;
+
+/// 保留的记录条数，超出时丢弃最早的
+pub const MAX_HISTORY: usize = 500;
+
+/// 一次播放
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub id: String,
+    pub url: String,
+    /// 来源接口，接口被删除后仍保留当时的 id 与名称
+    pub endpoint: String,
+    pub endpoint_name: String,
+    /// 播放时间，Unix 毫秒
+    pub played_at: u64,
+    /// 预加载时得到的内容指纹，直接播放或 HLS 时没有
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+}
+
+/// 供界面浏览的记录，附带预加载缓存中仍保留的字节数
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryItem {
+    #[serde(flatten)]
+    pub entry: HistoryEntry,
+    /// 缓存已被淘汰或是 HLS 时为 `None`，回放时需要重新下载
+    pub cached_bytes: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredHistory {
+    version: u32,
+    entries: Vec<HistoryEntry>,
+}
+
+struct HistoryState {
+    /// 越靠后越新
+    entries: Vec<HistoryEntry>,
+    /// 当前播放的记录下标，后退/前进在此基础上移动；本次运行还没有播放时为 `None`
+    cursor: Option<usize>,
+}
+
+pub struct PlaybackHistory {
+    path: PathBuf,
+    state: Mutex<HistoryState>,
+}
+
+impl PlaybackHistory {
+    pub fn load(path: PathBuf) -> Self {
+        let entries = load_versioned_json::<StoredHistory>(&path, HISTORY_VERSION)
+            .map(|stored| stored.entries)
+            .unwrap_or_default();
+        Self {
+            path,
+            state: Mutex::new(HistoryState {
+                entries,
+                cursor: None,
+            }),
+        }
+    }
+
+    /// 记录新播放的视频并作为当前位置；在后退途中播放新视频时，之后的记录保留在列表中
+    pub fn record(
+        &self,
+        url: &str,
+        endpoint_id: &str,
+        endpoint_name: &str,
+        fingerprint: Option<&str>,
+    ) -> HistoryEntry {
+        let entry = HistoryEntry {
+            id: Uuid::new_v4().to_string(),
+            url: url.to_string(),
+            endpoint: endpoint_id.to_string(),
+            endpoint_name: endpoint_name.to_string(),
+            played_at: now_millis() as u64,
+            fingerprint: fingerprint.map(|f| f.to_string()),
+        };
+        let mut state = self.state.lock().unwrap();
+        state.entries.push(entry.clone());
+        let overflow = state.entries.len().saturating_sub(MAX_HISTORY);
+        state.entries.drain(..overflow);
+        state.cursor = Some(state.entries.len() - 1);
+        self.save(&state.entries);
+        entry
+    }
+
+    /// 把最近 `window` 条记录的地址与指纹加入 `seen`
+    pub fn note_recent(&self, window: usize, seen: &mut SeenMedia) {
+        let state = self.state.lock().unwrap();
+        for entry in state.entries.iter().rev().take(window) {
+            seen.insert(&entry.url, entry.fingerprint.as_deref());
+        }
+    }
+
+    /// 当前播放的记录
+    pub fn current(&self) -> Option<HistoryEntry> {
+        let state = self.state.lock().unwrap();
+        state.entries.get(state.cursor?).cloned()
+    }
+
+    /// 上一条记录，已在最早一条时返回 `None`
+    pub fn back(&self) -> Option<HistoryEntry> {
+        let mut state = self.state.lock().unwrap();
+        let index = state.cursor?.checked_sub(1)?;
+        state.cursor = Some(index);
+        state.entries.get(index).cloned()
+    }
+
+    /// 后退之后的下一条记录，已在最新一条时返回 `None`
+    pub fn forward(&self) -> Option<HistoryEntry> {
+        let mut state = self.state.lock().unwrap();
+        let index = state.cursor? + 1;
+        let entry = state.entries.get(index).cloned()?;
+        state.cursor = Some(index);
+        Some(entry)
+    }
+
+    /// 跳到指定记录，之后的后退/前进从这里开始
+    pub fn seek(&self, id: &str) -> Option<HistoryEntry> {
+        let mut state = self.state.lock().unwrap();
+        let index = state.entries.iter().position(|e| e.id == id)?;
+        state.cursor = Some(index);
+        state.entries.get(index).cloned()
+    }
+
+    /// 地址或接口名称包含 `query` 的记录（不区分大小写），最新的在前
+    pub fn search(&self, query: &str, limit: usize) -> Vec<HistoryEntry> {
+        let query = query.trim().to_lowercase();
+        let state = self.state.lock().unwrap();
+        state
+            .entries
+            .iter()
+            .rev()
+            .filter(|e| {
+                query.is_empty()
+                    || e.url.to_lowercase().contains(&query)
+                    || e.endpoint_name.to_lowercase().contains(&query)
+            })
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.entries.clear();
+        state.cursor = None;
+        self.save(&state.entries);
+    }
+
+    /// 保存失败只记录日志，不影响播放
+    fn save(&self, entries: &[HistoryEntry]) {
+        let stored = StoredHistory {
+            version: HISTORY_VERSION,
+            entries: entries.to_vec(),
+        };
+        if let Err(e) = write_json_atomic(&self.path, &stored) {
+            eprintln!("history: 保存失败: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history() -> PlaybackHistory {
+        let path = std::env::temp_dir().join(format!("history-test-{}.json", Uuid::new_v4()));
+        PlaybackHistory::load(path)
+    }
+
+    fn url(entry: Option<HistoryEntry>) -> Option<String> {
+        entry.map(|e| e.url)
+    }
+
+    #[test]
+    fn moves_cursor_back_forward_and_seek() {
+        let history = history();
+        assert!(history.back().is_none());
+        let first = history.record("a", "e", "接口", None);
+        history.record("b", "e", "接口", None);
+        history.record("c", "e", "接口", None);
+
+        assert_eq!(url(history.forward()), None);
+        assert_eq!(url(history.back()), Some("b".into()));
+        assert_eq!(url(history.back()), Some("a".into()));
+        assert_eq!(url(history.back()), None);
+        assert_eq!(url(history.current()), Some("a".into()));
+        assert_eq!(url(history.forward()), Some("b".into()));
+
+        assert_eq!(url(history.seek(&first.id)), Some("a".into()));
+        assert_eq!(url(history.seek("missing")), None);
+        assert_eq!(url(history.current()), Some("a".into()));
+
+        // 后退途中播放新视频，新记录追加在最后并成为当前位置
+        history.record("d", "e", "接口", None);
+        assert_eq!(url(history.back()), Some("c".into()));
+        let _ = std::fs::remove_file(&history.path);
+    }
+
+    #[test]
+    fn trims_to_max_history() {
+        let history = history();
+        for i in 0..MAX_HISTORY + 2 {
+            history.record(&i.to_string(), "e", "接口", None);
+        }
+        let entries = history.search("", usize::MAX);
+        assert_eq!(entries.len(), MAX_HISTORY);
+        assert_eq!(entries.last().unwrap().url, "2");
+        assert_eq!(url(history.current()), Some((MAX_HISTORY + 1).to_string()));
+
+        let reloaded = PlaybackHistory::load(history.path.clone());
+        assert_eq!(reloaded.search("", usize::MAX).len(), MAX_HISTORY);
+        let _ = std::fs::remove_file(&history.path);
+    }
+
+    #[test]
+    fn notes_only_recent_entries() {
+        let history = history();
+        history.record("a", "e", "接口", Some("fa"));
+        history.record("b", "e", "接口", Some("fb"));
+        history.record("c", "e", "接口", None);
+
+        let mut seen = SeenMedia::default();
+        history.note_recent(2, &mut seen);
+        assert!(seen.has_url("b") && seen.has_url("c"));
+        assert!(!seen.has_url("a"));
+        assert!(seen.has_fingerprint("fb"));
+        assert!(!seen.has_fingerprint("fa"));
+
+        let mut seen = SeenMedia::default();
+        history.note_recent(0, &mut seen);
+        assert!(!seen.has_url("c"));
+        let _ = std::fs::remove_file(&history.path);
+    }
+}
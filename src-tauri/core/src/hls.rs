@@ -0,0 +1,460 @@
+//! HLS 播放：解析主/媒体播放列表，选出一路码率，并把分片地址改写到本地 `stream:///hls/...`
+
+use bytes::{Bytes, BytesMut};
+use reqwest::Url;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use crate::media::{MediaKind, MediaSpec, MB};
+use crate::mime::normalize_declared;
+use crate::resolver::{open_media, too_large};
+
+pub const HLS_CONTENT_TYPE: &str = "application/vnd.apple.mpegurl";
+
+/// 分片无法识别时使用的类型
+pub const SEGMENT_CONTENT_TYPE: &str = "video/mp2t";
+
+/// 预加载时提前下载的分片数
+pub const HLS_PRELOAD_SEGMENTS: usize = 3;
+
+/// 播放列表文本的大小上限
+const MAX_PLAYLIST_BYTES: usize = 2 * MB as usize;
+
+const PLAYLIST_TYPES: [&str; 4] = [
+    HLS_CONTENT_TYPE,
+    "application/x-mpegurl",
+    "audio/mpegurl",
+    "audio/x-mpegurl",
+];
+
+/// 按 URL 扩展名或声明的类型判断是否为 HLS 播放列表
+pub fn is_playlist(url: &str, content_type: Option<&str>) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    path.to_ascii_lowercase().ends_with(".m3u8")
+        || normalize_declared(content_type).is_some_and(|ct| PLAYLIST_TYPES.contains(&ct.as_str()))
+}
+
+/// 主播放列表中的一路码率
+#[derive(Debug)]
+pub struct Variant {
+    pub uri: String,
+    pub bandwidth: u64,
+}
+
+fn is_master(text: &str) -> bool {
+    text.lines()
+        .any(|line| line.starts_with("#EXT-X-STREAM-INF"))
+}
+
+fn check_header(text: &str) -> Result<(), String> {
+    if text
+        .trim_start_matches('\u{feff}')
+        .trim_start()
+        .starts_with("#EXTM3U")
+    {
+        Ok(())
+    } else {
+        Err("不是有效的 HLS 播放列表".into())
+    }
+}
+
+/// 解析 `KEY=VALUE,KEY="V,ALUE"` 形式的属性列表
+fn parse_attributes(list: &str) -> Vec<(&str, &str)> {
+    let mut attrs = Vec::new();
+    let mut rest = list;
+    while let Some((key, value)) = rest.split_once('=') {
+        let (value, next) = match value.strip_prefix('"') {
+            Some(quoted) => match quoted.split_once('"') {
+                Some((value, next)) => (value, next),
+                None => (quoted, ""),
+            },
+            None => value.split_once(',').map_or((value, ""), |(v, n)| (v, n)),
+        };
+        attrs.push((key.trim(), value));
+        rest = next.trim_start_matches(',');
+    }
+    attrs
+}
+
+fn resolve_uri(base: &Url, uri: &str) -> Result<String, String> {
+    base.join(uri.trim())
+        .map(String::from)
+        .map_err(|e| format!("无效的分片地址 {}: {}", uri, e))
+}
+
+/// 主播放列表中的全部码率，地址已按 `base` 转为绝对地址
+pub fn parse_variants(text: &str, base: &Url) -> Result<Vec<Variant>, String> {
+    check_header(text)?;
+    let mut variants = Vec::new();
+    let mut pending: Option<u64> = None;
+    for line in text.lines().map(str::trim) {
+        if let Some(attrs) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+            let bandwidth = parse_attributes(attrs)
+                .into_iter()
+                .find(|(key, _)| *key == "BANDWIDTH")
+                .and_then(|(_, value)| value.parse().ok())
+                .unwrap_or(0);
+            pending = Some(bandwidth);
+        } else if !line.is_empty() && !line.starts_with('#') {
+            if let Some(bandwidth) = pending.take() {
+                variants.push(Variant {
+                    uri: resolve_uri(base, line)?,
+                    bandwidth,
+                });
+            }
+        }
+    }
+    Ok(variants)
+}
+
+/// 选择码率最高的一路；WebView 内播放本地代理的内容，带宽不是瓶颈
+pub fn pick_variant(variants: &[Variant]) -> Option<&Variant> {
+    variants.iter().max_by_key(|v| v.bandwidth)
+}
+
+/// 改写后的媒体播放列表
+struct HlsState {
+    manifest: String,
+    /// 播放列表引用的分片、密钥与初始化段
+    resources: ResourceTable,
+    /// 按出现顺序引用的资源下标，`true` 表示是媒体分片
+    refs: Vec<(usize, bool)>,
+    /// 没有 `#EXT-X-ENDLIST` 的直播列表，播放器会反复刷新
+    live: bool,
+    encrypted: bool,
+}
+
+/// 资源地址与下标的双向映射，下标即 `stream:///hls/{id}/{n}` 中的 n，分配后不再复用
+#[derive(Clone, Default)]
+struct ResourceTable {
+    urls: HashMap<usize, String>,
+    indices: HashMap<String, usize>,
+    next: usize,
+}
+
+impl ResourceTable {
+    /// 已有的地址沿用原下标
+    fn index_of(&mut self, url: String) -> usize {
+        if let Some(&index) = self.indices.get(&url) {
+            return index;
+        }
+        let index = self.next;
+        self.next += 1;
+        self.urls.insert(index, url.clone());
+        self.indices.insert(url, index);
+        index
+    }
+
+    fn get(&self, index: usize) -> Option<&String> {
+        self.urls.get(&index)
+    }
+
+    /// 只保留 `keep` 中的下标
+    fn retain(&mut self, keep: &HashSet<usize>) {
+        self.urls.retain(|index, _| keep.contains(index));
+        self.indices.retain(|_, index| keep.contains(index));
+    }
+}
+
+/// 把媒体播放列表中的地址换成本地地址；`resources` 中已有的地址沿用原下标，刷新直播列表时保持稳定
+fn rewrite_media(
+    text: &str,
+    base: &Url,
+    session_id: &str,
+    mut resources: ResourceTable,
+) -> Result<HlsState, String> {
+    check_header(text)?;
+    if is_master(text) {
+        return Err("嵌套的主播放列表".into());
+    }
+
+    let local = |index: usize| format!("stream:///hls/{}/{}", session_id, index);
+    let mut manifest = String::with_capacity(text.len());
+    let mut refs = Vec::new();
+    let mut live = true;
+    let mut encrypted = false;
+
+    for line in text.trim_start_matches('\u{feff}').lines() {
+        let line = line.trim_end();
+        if line.starts_with("#EXT-X-ENDLIST") {
+            live = false;
+        }
+        if let Some(attrs) = line.strip_prefix("#EXT-X-KEY:") {
+            encrypted |= parse_attributes(attrs)
+                .iter()
+                .any(|(key, value)| *key == "METHOD" && *value != "NONE");
+        }
+
+        if line.is_empty() {
+            manifest.push('\n');
+            continue;
+        }
+        if !line.starts_with('#') {
+            let index = resources.index_of(resolve_uri(base, line)?);
+            refs.push((index, true));
+            manifest.push_str(&local(index));
+            manifest.push('\n');
+            continue;
+        }
+
+        // 标签中的 URI 属性（密钥、初始化段等）同样改写
+        match line.split_once("URI=\"") {
+            Some((head, rest)) => {
+                let (uri, tail) = rest.split_once('"').unwrap_or((rest, ""));
+                let index = resources.index_of(resolve_uri(base, uri)?);
+                refs.push((index, false));
+                manifest.push_str(&format!("{}URI=\"{}\"{}\n", head, local(index), tail));
+            }
+            None => {
+                manifest.push_str(line);
+                manifest.push('\n');
+            }
+        }
+    }
+
+    Ok(HlsState {
+        manifest,
+        resources,
+        refs,
+        live,
+        encrypted,
+    })
+}
+
+/// 一路 HLS 播放，分片通过 `stream:///hls/{id}/{n}` 代理
+pub struct HlsSession {
+    pub id: String,
+    /// 选中的媒体播放列表，已是重定向后的最终地址
+    pub playlist_url: String,
+    state: Mutex<HlsState>,
+}
+
+impl HlsSession {
+    pub fn new(id: String, playlist_url: String, text: &str) -> Result<Self, String> {
+        let base = Url::parse(&playlist_url).map_err(|e| format!("无效的播放列表地址: {}", e))?;
+        let state = rewrite_media(text, &base, &id, ResourceTable::default())?;
+        Ok(Self {
+            id,
+            playlist_url,
+            state: Mutex::new(state),
+        })
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.state.lock().unwrap().live
+    }
+
+    /// 用重新下载的直播列表替换当前内容；播放器可能还在请求上一版列表中的分片，
+    /// 只保留新旧两版引用的资源，其余的丢弃，长时间播放直播时不会无限增长
+    pub fn refresh(&self, text: &str) -> Result<(), String> {
+        let base = Url::parse(&self.playlist_url).map_err(|e| e.to_string())?;
+        let mut state = self.state.lock().unwrap();
+        let mut next = rewrite_media(text, &base, &self.id, state.resources.clone())?;
+        let keep: HashSet<usize> = state.refs.iter().chain(&next.refs).map(|r| r.0).collect();
+        next.resources.retain(&keep);
+        *state = next;
+        Ok(())
+    }
+
+    pub fn manifest(&self) -> String {
+        self.state.lock().unwrap().manifest.clone()
+    }
+
+    pub fn resource(&self, index: usize) -> Option<String> {
+        self.state.lock().unwrap().resources.get(index).cloned()
+    }
+
+    /// 播放前 `segments` 个分片所需的全部资源，按引用顺序去重
+    pub fn leading_resources(&self, segments: usize) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        let mut urls: Vec<String> = Vec::new();
+        let mut count = 0;
+        for &(index, is_segment) in &state.refs {
+            if count >= segments {
+                break;
+            }
+            let Some(url) = state.resources.get(index) else {
+                continue;
+            };
+            if !urls.contains(url) {
+                urls.push(url.clone());
+            }
+            count += usize::from(is_segment);
+        }
+        urls
+    }
+}
+
+/// 读取已打开的播放列表响应，返回最终地址与文本
+pub async fn read_playlist(mut resp: reqwest::Response) -> Result<(String, String), String> {
+    let url = resp.url().to_string();
+    let mut data = Vec::new();
+    while let Some(chunk) = resp
+        .chunk()
+        .await
+        .map_err(|e| format!("读取播放列表失败: {}", e))?
+    {
+        data.extend_from_slice(&chunk);
+        if data.len() > MAX_PLAYLIST_BYTES {
+            return Err("播放列表过大".into());
+        }
+    }
+    let text = String::from_utf8(data).map_err(|_| "播放列表不是有效的 UTF-8 文本".to_string())?;
+    Ok((url, text))
+}
+
+pub async fn fetch_playlist(url: &str) -> Result<(String, String), String> {
+    read_playlist(open_media(MediaKind::Video, url).await?).await
+}
+
+/// 主播放列表时再取选中码率的媒体播放列表
+async fn select_media_playlist(url: String, text: String) -> Result<(String, String), String> {
+    if !is_master(&text) {
+        return Ok((url, text));
+    }
+    let base = Url::parse(&url).map_err(|e| format!("无效的播放列表地址: {}", e))?;
+    let variants = parse_variants(&text, &base)?;
+    let variant = pick_variant(&variants).ok_or("主播放列表中没有可用的码率")?;
+    eprintln!("hls: 选用码率 {} -> {}", variant.bandwidth, variant.uri);
+    fetch_playlist(&variant.uri).await
+}
+
+/// 从已打开的播放列表响应建立播放会话
+pub async fn open_session(id: String, resp: reqwest::Response) -> Result<HlsSession, String> {
+    let (url, text) = read_playlist(resp).await?;
+    let (url, text) = select_media_playlist(url, text).await?;
+    HlsSession::new(id, url, &text)
+}
+
+/// 按顺序下载全部分片并拼接为单个文件，初始化段只写一次
+pub async fn download(resp: reqwest::Response, spec: &MediaSpec) -> Result<Bytes, String> {
+    let session = open_session(String::new(), resp).await?;
+    let (live, encrypted) = {
+        let state = session.state.lock().unwrap();
+        (state.live, state.encrypted)
+    };
+    if live {
+        return Err("直播流无法下载".into());
+    }
+    if encrypted {
+        return Err("暂不支持下载加密的 HLS 视频".into());
+    }
+
+    let mut data = BytesMut::new();
+    for url in session.leading_resources(usize::MAX) {
+        let mut resp = open_media(MediaKind::Video, &url).await?;
+        while let Some(chunk) = resp
+            .chunk()
+            .await
+            .map_err(|e| format!("读取分片失败: {}", e))?
+        {
+            data.extend_from_slice(&chunk);
+            if data.len() as u64 > spec.max_bytes {
+                return Err(too_large(spec));
+            }
+        }
+    }
+    eprintln!("hls: 下载完成 {} 字节", data.len());
+    Ok(data.freeze())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_highest_bandwidth_variant() {
+        let text = "#EXTM3U\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=800000,CODECS=\"avc1,mp4a\"\nlow/index.m3u8\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=2400000\nhttps://cdn.example/high.m3u8\n";
+        let base = Url::parse("https://example.com/live/master.m3u8").unwrap();
+        let variants = parse_variants(text, &base).unwrap();
+        assert_eq!(variants[0].uri, "https://example.com/live/low/index.m3u8");
+        assert_eq!(
+            pick_variant(&variants).unwrap().uri,
+            "https://cdn.example/high.m3u8"
+        );
+    }
+
+    #[test]
+    fn rewrites_relative_and_absolute_uris() {
+        let text = "#EXTM3U\n\
+            #EXT-X-MAP:URI=\"init.mp4\"\n\
+            #EXTINF:4,\nseg0.ts\n\
+            #EXTINF:4,\n/abs/seg1.ts\n\
+            #EXTINF:4,\nhttps://other.example/seg2.ts\n\
+            #EXTINF:4,\nseg0.ts\n\
+            #EXT-X-ENDLIST\n";
+        let session =
+            HlsSession::new("s".into(), "https://example.com/v/index.m3u8".into(), text).unwrap();
+
+        let manifest = session.manifest();
+        assert!(manifest.contains("#EXT-X-MAP:URI=\"stream:///hls/s/0\""));
+        assert!(manifest.contains("stream:///hls/s/1\n"));
+        assert!(!manifest.contains("seg0.ts"));
+        assert!(!session.is_live());
+        assert_eq!(
+            session.resource(0).unwrap(),
+            "https://example.com/v/init.mp4"
+        );
+        assert_eq!(
+            session.resource(1).unwrap(),
+            "https://example.com/v/seg0.ts"
+        );
+        assert_eq!(
+            session.resource(2).unwrap(),
+            "https://example.com/abs/seg1.ts"
+        );
+        assert_eq!(
+            session.resource(3).unwrap(),
+            "https://other.example/seg2.ts"
+        );
+        assert_eq!(session.resource(4), None);
+        // 重复引用的分片沿用同一下标
+        assert_eq!(session.leading_resources(2).len(), 3);
+    }
+
+    #[test]
+    fn refresh_keeps_indices_and_drops_old_segments() {
+        let live = |segments: &[&str]| {
+            let mut text = "#EXTM3U\n".to_string();
+            for segment in segments {
+                text.push_str(&format!("#EXTINF:4,\n{}\n", segment));
+            }
+            text
+        };
+        let session = HlsSession::new(
+            "s".into(),
+            "https://example.com/live.m3u8".into(),
+            &live(&["a.ts", "b.ts"]),
+        )
+        .unwrap();
+        assert!(session.is_live());
+
+        session.refresh(&live(&["b.ts", "c.ts"])).unwrap();
+        assert!(session.manifest().contains("stream:///hls/s/1\n"));
+        assert_eq!(session.resource(2).unwrap(), "https://example.com/c.ts");
+        // 上一版列表中的分片仍可请求
+        assert_eq!(session.resource(0).unwrap(), "https://example.com/a.ts");
+
+        session.refresh(&live(&["c.ts", "d.ts"])).unwrap();
+        assert_eq!(session.resource(0), None);
+        assert_eq!(session.resource(1).unwrap(), "https://example.com/b.ts");
+        assert_eq!(session.resource(3).unwrap(), "https://example.com/d.ts");
+    }
+
+    #[test]
+    fn rejects_nested_master_and_missing_header() {
+        let base = Url::parse("https://example.com/a.m3u8").unwrap();
+        assert!(rewrite_media(
+            "#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=1\na.m3u8\n",
+            &base,
+            "s",
+            ResourceTable::default()
+        )
+        .is_err());
+        assert!(rewrite_media("<html>", &base, "s", ResourceTable::default()).is_err());
+        assert!(is_playlist("https://a/b.M3U8?x=1", None));
+        assert!(is_playlist("https://a/b", Some("application/x-mpegURL")));
+    }
+}
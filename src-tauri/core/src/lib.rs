@@ -0,0 +1,29 @@
+//! 播放器的核心逻辑：接口目录、地址解析、下载、预加载队列与磁盘缓存、Range 处理。
+//!
+//! 不依赖 Tauri，桌面端的命令只是 [`Engine`] 之上的薄适配层。
+
+pub mod builtin;
+pub mod cache;
+pub mod catalog;
+pub mod dedup;
+pub mod engine;
+pub mod extract;
+pub mod favorites;
+pub mod history;
+pub mod hls;
+pub mod media;
+pub mod mime;
+pub mod model;
+pub mod phash;
+pub mod preload;
+pub mod proxy;
+pub mod range;
+pub mod remote;
+pub mod resolver;
+pub mod scheduler;
+pub mod settings;
+
+pub use catalog::{Catalog, CatalogBundle, ImportMode, ImportSummary};
+pub use engine::Engine;
+pub use media::{MediaKind, MediaSpec};
+pub use model::{ApiCategory, ApiEndpoint, ExtractRule, ResponseKind, SuccessRule};
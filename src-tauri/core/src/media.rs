@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::builtin::{get_builtin_categories, get_builtin_image_categories};
+use crate::extract::{extract_image_url_from_json, extract_video_url_from_json};
+use crate::model::ApiCategory;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaKind {
+    Video,
+    Image,
+}
+
+impl MediaKind {
+    pub const ALL: [MediaKind; 2] = [MediaKind::Video, MediaKind::Image];
+
+    pub fn spec(self) -> &'static MediaSpec {
+        match self {
+            MediaKind::Video => &VIDEO_SPEC,
+            MediaKind::Image => &IMAGE_SPEC,
+        }
+    }
+}
+
+/// 各媒体类型之间的全部差异，新增一种媒体只需补一项
+pub struct MediaSpec {
+    /// 用于日志与错误提示
+    pub label: &'static str,
+    pub builtin_categories: fn() -> Vec<ApiCategory>,
+    pub category_prefix: &'static str,
+    pub endpoint_prefix: &'static str,
+    /// 自动识别时视为直链的 Content-Type 前缀
+    pub content_type_prefix: &'static str,
+    /// 无法识别时使用的 Content-Type
+    pub default_content_type: &'static str,
+    /// 自动识别时视为直链的 URL 扩展名
+    pub url_extensions: &'static [&'static str],
+    /// HTML 中携带 `src` 的标签
+    pub html_tags: &'static [&'static str],
+    /// HTML 中的 og 属性
+    pub og_properties: &'static [&'static str],
+    /// 未配置提取规则时的 JSON 解析方式
+    pub json_fallback: fn(&Value) -> Result<String, String>,
+    pub referer: Option<&'static str>,
+    pub max_bytes: u64,
+    pub download_timeout_secs: u64,
+    pub connect_timeout_secs: u64,
+}
+
+pub const MB: u64 = 1024 * 1024;
+
+const VIDEO_SPEC: MediaSpec = MediaSpec {
+    label: "视频",
+    builtin_categories: get_builtin_categories,
+    category_prefix: "custom_cat_",
+    endpoint_prefix: "custom_ep_",
+    content_type_prefix: "video/",
+    default_content_type: "video/mp4",
+    url_extensions: &[".mp4", ".webm", ".m3u8"],
+    html_tags: &["video", "source"],
+    og_properties: &["og:video:url", "og:video"],
+    json_fallback: extract_video_url_from_json,
+    referer: Some("https://api.tzjsy.cn/"),
+    max_bytes: 100 * MB,
+    download_timeout_secs: 60,
+    connect_timeout_secs: 15,
+};
+
+const IMAGE_SPEC: MediaSpec = MediaSpec {
+    label: "图片",
+    builtin_categories: get_builtin_image_categories,
+    category_prefix: "custom_img_cat_",
+    endpoint_prefix: "custom_img_ep_",
+    content_type_prefix: "image/",
+    default_content_type: "image/jpeg",
+    url_extensions: &[".jpg", ".jpeg", ".png", ".webp", ".gif", ".bmp"],
+    html_tags: &["img"],
+    og_properties: &["og:image"],
+    json_fallback: |value| {
+        extract_image_url_from_json(value).ok_or_else(|| "JSON中未找到图片地址".to_string())
+    },
+    referer: None,
+    max_bytes: 15 * MB,
+    download_timeout_secs: 30,
+    connect_timeout_secs: 10,
+};
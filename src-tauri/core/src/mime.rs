@@ -0,0 +1,169 @@
+//! 媒体类型识别：优先按文件头判断，识别不了再用服务器声明的类型
+
+/// 识别所需的最少字节数，MPEG-TS 需要连续三个包头
+pub const SNIFF_LEN: usize = 512;
+
+const TS_PACKET: usize = 188;
+
+/// 按文件头识别常见的视频、图片与播放列表格式
+pub fn sniff(head: &[u8]) -> Option<&'static str> {
+    if head.len() >= 12 && &head[4..8] == b"ftyp" {
+        return Some(match &head[8..12] {
+            b"qt  " => "video/quicktime",
+            brand if brand.starts_with(b"3g2") => "video/3gpp2",
+            brand if brand.starts_with(b"3g") => "video/3gpp",
+            b"avif" | b"avis" => "image/avif",
+            b"heic" | b"heix" | b"mif1" | b"msf1" => "image/heic",
+            _ => "video/mp4",
+        });
+    }
+
+    if head.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        // EBML 头中的 DocType 决定是 WebM 还是 Matroska
+        let doc = &head[..head.len().min(64)];
+        let is_webm = doc.windows(4).any(|w| w == b"webm");
+        return Some(if is_webm {
+            "video/webm"
+        } else {
+            "video/x-matroska"
+        });
+    }
+
+    if head.len() >= 12 && head.starts_with(b"RIFF") {
+        match &head[8..12] {
+            b"AVI " => return Some("video/x-msvideo"),
+            b"WEBP" => return Some("image/webp"),
+            _ => {}
+        }
+    }
+
+    // BMP 的魔数只有两个字节，再校验保留字段以免误判
+    if head.len() >= 14 && head.starts_with(b"BM") && head[6..10] == [0, 0, 0, 0] {
+        return Some("image/bmp");
+    }
+
+    let prefixes: [(&[u8], &str); 9] = [
+        (b"FLV\x01", "video/x-flv"),
+        (b"OggS", "video/ogg"),
+        (&[0x00, 0x00, 0x01, 0xBA], "video/mpeg"),
+        (b"#EXTM3U", "application/vnd.apple.mpegurl"),
+        (&[0xFF, 0xD8, 0xFF], "image/jpeg"),
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        // 个别服务器在 m3u8 前带 BOM
+        (b"\xEF\xBB\xBF#EXTM3U", "application/vnd.apple.mpegurl"),
+    ];
+    if let Some((_, mime)) = prefixes.iter().find(|(magic, _)| head.starts_with(magic)) {
+        return Some(mime);
+    }
+
+    // MPEG-TS：每 188 字节一个 0x47 同步字节
+    if head.len() > TS_PACKET * 2 && (0..3).all(|i| head[i * TS_PACKET] == 0x47) {
+        return Some("video/mp2t");
+    }
+
+    None
+}
+
+/// 去掉参数并转为小写，通用的二进制类型视为未声明
+pub fn normalize_declared(declared: Option<&str>) -> Option<String> {
+    let mime = declared?
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    match mime.as_str() {
+        ""
+        | "application/octet-stream"
+        | "binary/octet-stream"
+        | "application/binary"
+        | "application/unknown"
+        | "text/plain" => None,
+        _ => Some(mime),
+    }
+}
+
+/// 文件头可识别时以文件头为准，其次是服务器声明的类型，最后使用 `fallback`
+pub fn resolve_content_type(declared: Option<&str>, head: &[u8], fallback: &str) -> String {
+    sniff(head)
+        .map(str::to_string)
+        .or_else(|| normalize_declared(declared))
+        .unwrap_or_else(|| fallback.to_string())
+}
+
+/// 保存文件时使用的扩展名
+pub fn extension_for(content_type: &str) -> Option<&'static str> {
+    Some(match content_type {
+        "video/mp4" => "mp4",
+        "video/quicktime" => "mov",
+        "video/3gpp" => "3gp",
+        "video/3gpp2" => "3g2",
+        "video/webm" => "webm",
+        "video/x-matroska" => "mkv",
+        "video/x-msvideo" => "avi",
+        "video/x-flv" => "flv",
+        "video/ogg" => "ogv",
+        "video/mpeg" => "mpg",
+        "video/mp2t" => "ts",
+        "image/jpeg" => "jpg",
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/bmp" => "bmp",
+        "image/avif" => "avif",
+        "image/heic" => "heic",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HLS_TYPE: &str = "application/vnd.apple.mpegurl";
+
+    #[test]
+    fn sniffs_containers() {
+        assert_eq!(sniff(b"\0\0\0\x18ftypisom\0\0\0\0"), Some("video/mp4"));
+        assert_eq!(
+            sniff(b"\0\0\0\x14ftypqt  \0\0\0\0"),
+            Some("video/quicktime")
+        );
+        assert_eq!(sniff(b"\0\0\0\x1cftypavif\0\0\0\0"), Some("image/avif"));
+        assert_eq!(sniff(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\0\0"), Some("image/png"));
+        assert_eq!(sniff(b"\xEF\xBB\xBF#EXTM3U\n"), Some(HLS_TYPE));
+        assert_eq!(sniff(b"<html>"), None);
+    }
+
+    #[test]
+    fn sniffs_webm_and_ts() {
+        let mut webm = vec![0x1A, 0x45, 0xDF, 0xA3];
+        webm.extend_from_slice(b"\x42\x82\x84webm");
+        assert_eq!(sniff(&webm), Some("video/webm"));
+
+        let mut ts = vec![0u8; TS_PACKET * 3];
+        for i in 0..3 {
+            ts[i * TS_PACKET] = 0x47;
+        }
+        assert_eq!(sniff(&ts), Some("video/mp2t"));
+    }
+
+    #[test]
+    fn header_wins_over_declared_type() {
+        assert_eq!(
+            resolve_content_type(Some("text/plain"), b"\xFF\xD8\xFF\xE0", "video/mp4"),
+            "image/jpeg"
+        );
+        assert_eq!(
+            resolve_content_type(Some("Video/WebM; codecs=vp9"), b"", "video/mp4"),
+            "video/webm"
+        );
+        assert_eq!(
+            resolve_content_type(Some("application/octet-stream"), b"", "video/mp4"),
+            "video/mp4"
+        );
+    }
+}
@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ApiEndpoint {
+    pub id: String,
+    pub name: String,
+    pub url: String,
+    pub builtin: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extract: Option<ExtractRule>,
+    #[serde(default, skip_serializing_if = "ResponseKind::is_auto")]
+    pub kind: ResponseKind,
+}
+
+/// 接口响应类型，`Auto` 为按状态码、Content-Type 与响应内容推断
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseKind {
+    #[default]
+    Auto,
+    /// 302 等重定向到媒体地址
+    Redirect,
+    /// JSON 中包含媒体地址
+    Json,
+    /// 接口地址本身即媒体流
+    Stream,
+    /// 响应体为纯文本 URL
+    Text,
+    /// HTML 页面中的 `<video>`/`<img>` 或 og 标签
+    Html,
+}
+
+impl ResponseKind {
+    pub fn is_auto(&self) -> bool {
+        *self == ResponseKind::Auto
+    }
+}
+
+/// 接口响应的提取规则，优先于内置的启发式解析
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct ExtractRule {
+    /// JSON Pointer（`/data/0/url`）或点路径（`data.list[0].url`），指向数组时随机取一项
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// 成功条件，如 `code` 等于 `200`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub success: Option<SuccessRule>,
+    /// 用于纯文本响应，有捕获组时取第一个捕获组
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SuccessRule {
+    pub path: String,
+    pub equals: Value,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ApiCategory {
+    pub id: String,
+    pub name: String,
+    pub builtin: bool,
+    pub endpoints: Vec<ApiEndpoint>,
+}
+
+pub const RANDOM_CATEGORY: &str = "random";
+
+pub fn now_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
@@ -0,0 +1,348 @@
+//! 图片感知哈希：识别重新编码或缩放过的相同图片
+//!
+//! 预加载下载的图片会计算哈希并记入设置文件旁的索引，新图片与最近的图片
+//! 汉明距离不超过设定值时视为重复。收藏的图片也据此分组列出相似的几张。
+
+use std::f64::consts::PI;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use image::imageops::{self, FilterType};
+use image::GrayImage;
+use serde::{Deserialize, Serialize};
+
+use crate::model::now_millis;
+use crate::settings::{load_versioned_json, write_json_atomic};
+
+pub const IMAGE_HASH_FILE: &str = "image_hashes.json";
+const IMAGE_HASH_VERSION: u32 = 1;
+
+/// 索引保留的条数，超出时丢弃最早的
+pub const MAX_IMAGE_HASHES: usize = 1000;
+
+/// 默认视为相似的最大汉明距离
+pub const DEFAULT_IMAGE_DISTANCE: u32 = 6;
+
+/// 汉明距离设置的上限，再大基本任意两张图都会被当成相似
+pub const MAX_IMAGE_DISTANCE: u32 = 20;
+
+/// pHash 先缩到的边长
+const DCT_SIZE: usize = 32;
+
+/// 三种哈希都是 64 位
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    /// 均值哈希：最快，对亮度变化敏感
+    AHash,
+    /// 差异哈希：比较相邻像素的明暗
+    #[default]
+    DHash,
+    /// 离散余弦变换哈希：最慢，对缩放与压缩最稳定
+    PHash,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageHash {
+    pub algorithm: HashAlgorithm,
+    pub bits: u64,
+}
+
+impl ImageHash {
+    /// 解码图片并计算哈希，动图只取第一帧
+    pub fn compute(data: &[u8], algorithm: HashAlgorithm) -> Result<Self, String> {
+        let gray = image::load_from_memory(data)
+            .map_err(|e| format!("解码图片失败: {}", e))?
+            .to_luma8();
+        let bits = match algorithm {
+            HashAlgorithm::AHash => average_hash(&gray),
+            HashAlgorithm::DHash => difference_hash(&gray),
+            HashAlgorithm::PHash => perceptual_hash(&gray),
+        };
+        Ok(Self { algorithm, bits })
+    }
+
+    /// 汉明距离，算法不同时无法比较
+    pub fn distance(&self, other: &ImageHash) -> Option<u32> {
+        (self.algorithm == other.algorithm).then(|| (self.bits ^ other.bits).count_ones())
+    }
+}
+
+/// 把 `values` 中大于 `threshold` 的位置记为 1
+fn bits_above(values: impl Iterator<Item = f64>, threshold: f64) -> u64 {
+    values.enumerate().fold(
+        0,
+        |bits, (i, v)| if v > threshold { bits | 1 << i } else { bits },
+    )
+}
+
+fn average_hash(gray: &GrayImage) -> u64 {
+    let small = imageops::resize(gray, 8, 8, FilterType::Triangle);
+    let pixels: Vec<f64> = small.pixels().map(|p| f64::from(p.0[0])).collect();
+    let mean = pixels.iter().sum::<f64>() / pixels.len() as f64;
+    bits_above(pixels.into_iter(), mean)
+}
+
+fn difference_hash(gray: &GrayImage) -> u64 {
+    let small = imageops::resize(gray, 9, 8, FilterType::Triangle);
+    let mut bits = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            let left = small.get_pixel(x, y).0[0];
+            let right = small.get_pixel(x + 1, y).0[0];
+            if right > left {
+                bits |= 1 << (y * 8 + x);
+            }
+        }
+    }
+    bits
+}
+
+/// 缩到 32×32 后做二维 DCT，取左上角 8×8 的低频系数与中位数比较
+fn perceptual_hash(gray: &GrayImage) -> u64 {
+    let small = imageops::resize(gray, DCT_SIZE as u32, DCT_SIZE as u32, FilterType::Triangle);
+    let pixels: Vec<f64> = small.pixels().map(|p| f64::from(p.0[0])).collect();
+
+    // cos_table[k][n] = cos(π(2n+1)k / 2N)，只需要前 8 个频率
+    let cos_table: Vec<Vec<f64>> = (0..8)
+        .map(|k| {
+            (0..DCT_SIZE)
+                .map(|n| (PI * (2 * n + 1) as f64 * k as f64 / (2 * DCT_SIZE) as f64).cos())
+                .collect()
+        })
+        .collect();
+
+    // 先对每一行做变换，再对列做变换
+    let rows: Vec<Vec<f64>> = (0..DCT_SIZE)
+        .map(|y| {
+            let row = &pixels[y * DCT_SIZE..(y + 1) * DCT_SIZE];
+            cos_table
+                .iter()
+                .map(|c| row.iter().zip(c).map(|(p, c)| p * c).sum())
+                .collect()
+        })
+        .collect();
+    let mut low = Vec::with_capacity(64);
+    for c in &cos_table {
+        for u in 0..8 {
+            low.push(rows.iter().zip(c).map(|(row, c)| row[u] * c).sum::<f64>());
+        }
+    }
+
+    let mut sorted = low.clone();
+    sorted.sort_by(f64::total_cmp);
+    let median = (sorted[31] + sorted[32]) / 2.0;
+    bits_above(low.into_iter(), median)
+}
+
+/// 在阻塞线程池中读取文件并计算哈希
+pub async fn hash_file(path: PathBuf, algorithm: HashAlgorithm) -> Result<ImageHash, String> {
+    tokio::task::spawn_blocking(move || {
+        let data = fs::read(&path).map_err(|e| format!("读取图片失败: {}", e))?;
+        ImageHash::compute(&data, algorithm)
+    })
+    .await
+    .map_err(|e| format!("计算感知哈希失败: {}", e))?
+}
+
+/// 把距离不超过 `distance` 的哈希连成组，返回至少有两个成员的组（下标）
+pub fn cluster(hashes: &[ImageHash], distance: u32) -> Vec<Vec<usize>> {
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+
+    let mut parent: Vec<usize> = (0..hashes.len()).collect();
+    for i in 0..hashes.len() {
+        for j in i + 1..hashes.len() {
+            if hashes[i]
+                .distance(&hashes[j])
+                .is_some_and(|d| d <= distance)
+            {
+                let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                parent[b] = a;
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_of = vec![usize::MAX; hashes.len()];
+    for i in 0..hashes.len() {
+        let r = root(&mut parent, i);
+        if group_of[r] == usize::MAX {
+            group_of[r] = groups.len();
+            groups.push(Vec::new());
+        }
+        groups[group_of[r]].push(i);
+    }
+    groups.retain(|g| g.len() > 1);
+    groups
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IndexedImage {
+    url: String,
+    hash: ImageHash,
+    /// 记入索引的时间，Unix 毫秒
+    seen_at: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredIndex {
+    version: u32,
+    entries: Vec<IndexedImage>,
+}
+
+/// 看过的图片的哈希，越靠后越新
+pub struct ImageHashIndex {
+    path: PathBuf,
+    entries: Mutex<Vec<IndexedImage>>,
+}
+
+impl ImageHashIndex {
+    pub fn load(path: PathBuf) -> Self {
+        let entries = load_versioned_json::<StoredIndex>(&path, IMAGE_HASH_VERSION)
+            .map(|stored| stored.entries)
+            .unwrap_or_default();
+        Self {
+            path,
+            entries: Mutex::new(entries),
+        }
+    }
+
+    /// 最近 `window` 条中有距离不超过 `distance` 的图片时返回其地址，否则记入索引
+    pub fn insert_unless_near(
+        &self,
+        url: &str,
+        hash: ImageHash,
+        window: usize,
+        distance: u32,
+    ) -> Option<String> {
+        let mut entries = self.entries.lock().unwrap();
+        let similar = entries
+            .iter()
+            .rev()
+            .take(window)
+            .find(|e| e.hash.distance(&hash).is_some_and(|d| d <= distance));
+        if let Some(similar) = similar {
+            return Some(similar.url.clone());
+        }
+
+        entries.push(IndexedImage {
+            url: url.to_string(),
+            hash,
+            seen_at: now_millis() as u64,
+        });
+        let overflow = entries.len().saturating_sub(MAX_IMAGE_HASHES);
+        entries.drain(..overflow);
+        self.save(&entries);
+        None
+    }
+
+    fn save(&self, entries: &[IndexedImage]) {
+        let stored = StoredIndex {
+            version: IMAGE_HASH_VERSION,
+            entries: entries.to_vec(),
+        };
+        if let Err(e) = write_json_atomic(&self.path, &stored) {
+            eprintln!("phash: 保存失败: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageFormat, Rgb, RgbImage};
+    use std::io::Cursor;
+
+    fn hash(bits: u64) -> ImageHash {
+        ImageHash {
+            algorithm: HashAlgorithm::DHash,
+            bits,
+        }
+    }
+
+    fn encode(image: &RgbImage, format: ImageFormat) -> Vec<u8> {
+        let mut data = Cursor::new(Vec::new());
+        image.write_to(&mut data, format).unwrap();
+        data.into_inner()
+    }
+
+    fn pattern(width: u32, height: u32, seed: u32) -> RgbImage {
+        RgbImage::from_fn(width, height, |x, y| {
+            let (u, v) = (x * 400 / width, y * 300 / height);
+            let dx = u as i32 - 200;
+            let dy = v as i32 - 150;
+            if dx * dx + dy * dy < 90 * 90 {
+                Rgb([250, 240, 10])
+            } else if (u / (20 + seed)).is_multiple_of(2) {
+                Rgb([(u % 255) as u8, 100, 40])
+            } else {
+                Rgb([20, (v % 255) as u8, 90])
+            }
+        })
+    }
+
+    #[test]
+    fn distance_requires_same_algorithm() {
+        assert_eq!(hash(0b1011).distance(&hash(0b0001)), Some(2));
+        let other = ImageHash {
+            algorithm: HashAlgorithm::AHash,
+            bits: 0,
+        };
+        assert_eq!(hash(0).distance(&other), None);
+    }
+
+    #[test]
+    fn cluster_links_transitively() {
+        let hashes = [
+            hash(0),
+            hash(0b1),
+            hash(0b11),
+            hash(u64::MAX),
+            hash(0b111 << 60),
+        ];
+        assert_eq!(cluster(&hashes, 1), vec![vec![0, 1, 2]]);
+        assert_eq!(cluster(&hashes, 0), Vec::<Vec<usize>>::new());
+    }
+
+    #[test]
+    fn hashes_survive_resize_and_reencode() {
+        let original = encode(&pattern(400, 300, 0), ImageFormat::Png);
+        let resized = encode(&pattern(200, 150, 0), ImageFormat::Jpeg);
+        let different = encode(
+            &imageops::rotate180(&pattern(400, 300, 17)),
+            ImageFormat::Png,
+        );
+        for algorithm in [
+            HashAlgorithm::AHash,
+            HashAlgorithm::DHash,
+            HashAlgorithm::PHash,
+        ] {
+            let a = ImageHash::compute(&original, algorithm).unwrap();
+            let b = ImageHash::compute(&resized, algorithm).unwrap();
+            let c = ImageHash::compute(&different, algorithm).unwrap();
+            assert!(
+                a.distance(&b).unwrap() <= DEFAULT_IMAGE_DISTANCE,
+                "{:?}",
+                algorithm
+            );
+            assert!(
+                a.distance(&c).unwrap() > DEFAULT_IMAGE_DISTANCE,
+                "{:?}",
+                algorithm
+            );
+        }
+    }
+
+    #[test]
+    fn undecodable_data_is_an_error() {
+        assert!(ImageHash::compute(b"not an image", HashAlgorithm::DHash).is_err());
+    }
+}
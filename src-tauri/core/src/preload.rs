@@ -0,0 +1,1172 @@
+use std::collections::{HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cache::{read_file_range, CachedFile, DiskCache, PRELOAD_CACHE_BUDGET};
+use crate::dedup::{SeenMedia, DEFAULT_DEDUP_WINDOW};
+use crate::history::MAX_HISTORY;
+use crate::hls::{HlsSession, HLS_CONTENT_TYPE, HLS_PRELOAD_SEGMENTS};
+use crate::phash::{HashAlgorithm, DEFAULT_IMAGE_DISTANCE, MAX_IMAGE_DISTANCE};
+use crate::proxy::PROXY_WINDOW;
+use crate::range::{
+    content_range, evaluate, multipart_body, parse_range_header, unsatisfied_range, ByteRangeSpec,
+    RangeRequest,
+};
+use crate::remote::RemoteImage;
+
+/// 队列深度的上限
+pub const MAX_PRELOAD_DEPTH: usize = 20;
+
+/// 同时下载数的上限
+pub const MAX_PRELOAD_CONCURRENCY: usize = 8;
+
+/// 预加载的运行时设置，随其他设置一起保存
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PreloadSettings {
+    /// 视频队列深度，0 表示不预加载
+    pub video_depth: usize,
+    /// 图片队列深度，0 表示不预加载
+    pub image_depth: usize,
+    /// 视频、图片各自同时进行的下载数
+    pub concurrency: usize,
+    /// 视频预加载缓存的总字节数，达到后不再开始新的预加载
+    pub budget_bytes: u64,
+    /// 预加载时跳过最近多少条播放记录中出现过的视频，0 表示只跳过队列中已有的
+    pub dedup_window: usize,
+    /// 图片去重使用的感知哈希
+    pub image_hash: HashAlgorithm,
+    /// 汉明距离不超过该值的图片视为相似
+    pub image_distance: u32,
+}
+
+impl Default for PreloadSettings {
+    fn default() -> Self {
+        Self {
+            video_depth: 2,
+            image_depth: 3,
+            concurrency: 1,
+            budget_bytes: PRELOAD_CACHE_BUDGET,
+            dedup_window: DEFAULT_DEDUP_WINDOW,
+            image_hash: HashAlgorithm::default(),
+            image_distance: DEFAULT_IMAGE_DISTANCE,
+        }
+    }
+}
+
+impl PreloadSettings {
+    /// 把超出范围的值收敛到允许的区间
+    pub fn normalize(&mut self) {
+        self.video_depth = self.video_depth.min(MAX_PRELOAD_DEPTH);
+        self.image_depth = self.image_depth.min(MAX_PRELOAD_DEPTH);
+        self.concurrency = self.concurrency.clamp(1, MAX_PRELOAD_CONCURRENCY);
+        self.dedup_window = self.dedup_window.min(MAX_HISTORY);
+        self.image_distance = self.image_distance.min(MAX_IMAGE_DISTANCE);
+    }
+}
+
+/// 播放时等待尚未下载到的数据的最长时间
+pub const STREAM_WAIT_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// 预加载条目解析自哪个分类与接口，切换分类后据此判断是否仍可用
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PreloadSource {
+    pub category: String,
+    pub endpoint: String,
+    pub endpoint_name: String,
+}
+
+/// 预加载的视频
+pub struct PreloadedVideo {
+    pub id: String,
+    pub url: String,
+    pub source: PreloadSource,
+    pub media: PreloadedMedia,
+    /// 固定的条目在切换分类时不会被丢弃。只影响分类切换：仍可手动移除，
+    /// 缓存淘汰对队列中的条目一视同仁（都不淘汰），出队播放结束后也不再特别保留
+    pub pinned: bool,
+    /// 文件开头的内容指纹，HLS 没有
+    pub fingerprint: Option<String>,
+}
+
+/// 出队后交给播放的条目
+pub struct PoppedVideo {
+    pub id: String,
+    pub url: String,
+    pub source: PreloadSource,
+    pub fingerprint: Option<String>,
+    pub hls: Option<Arc<HlsSession>>,
+}
+
+/// 队列中一个条目的概况，供界面展示
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueEntry {
+    pub id: String,
+    pub url: String,
+    pub category: String,
+    pub endpoint: String,
+    pub endpoint_name: String,
+    pub content_type: String,
+    /// 文件总长度，尚未知道或是 HLS 时为 `None`
+    pub size: Option<u64>,
+    /// 已下载的字节数，HLS 为 0
+    pub downloaded: u64,
+    pub finished: bool,
+    pub error: Option<String>,
+    pub pinned: bool,
+}
+
+/// 已入队的预加载条目
+#[derive(Clone, Debug)]
+pub struct QueuedVideo {
+    pub id: String,
+    pub url: String,
+}
+
+/// 一次视频预加载的结果
+#[derive(Clone, Debug)]
+pub enum PreloadOutcome {
+    Queued(QueuedVideo),
+    /// 下载期间切换了分类，或条目已被移除，结果本就要丢弃，不算失败
+    Cancelled,
+}
+
+pub enum PreloadedMedia {
+    /// 单个文件，保存在缓存目录中，可能仍在下载
+    File {
+        key: String,
+        path: PathBuf,
+        /// 按文件头与上游声明确定的类型
+        content_type: String,
+        progress: Arc<DownloadProgress>,
+    },
+    /// HLS 播放列表，开头几个分片已在缓存中；会话 id 与条目 id 相同
+    Hls(Arc<HlsSession>),
+}
+
+#[derive(Default)]
+struct ProgressState {
+    downloaded: u64,
+    total: Option<u64>,
+    finished: bool,
+    error: Option<String>,
+}
+
+/// 后台下载的进度，播放端据此等待尚未写入的数据
+#[derive(Default)]
+pub struct DownloadProgress {
+    state: Mutex<ProgressState>,
+    changed: Condvar,
+    cancelled: AtomicBool,
+}
+
+impl DownloadProgress {
+    /// `total` 为响应声明的长度，未知时为 `None`
+    pub fn new(total: Option<u64>) -> Self {
+        Self {
+            state: Mutex::new(ProgressState {
+                total,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// 已在缓存中的完整文件
+    pub fn completed(len: u64) -> Self {
+        Self {
+            state: Mutex::new(ProgressState {
+                downloaded: len,
+                total: Some(len),
+                finished: true,
+                error: None,
+            }),
+            ..Default::default()
+        }
+    }
+
+    pub fn advance(&self, downloaded: u64) {
+        self.state.lock().unwrap().downloaded = downloaded;
+        self.changed.notify_all();
+    }
+
+    /// 下载结束，成功时以实际长度为准
+    pub fn finish(&self, result: Result<u64, String>) {
+        let mut state = self.state.lock().unwrap();
+        state.finished = true;
+        match result {
+            Ok(len) => {
+                state.downloaded = len;
+                state.total = Some(len);
+            }
+            Err(e) => state.error = Some(e),
+        }
+        drop(state);
+        self.changed.notify_all();
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.changed.notify_all();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// 等待直到 `ready(state)` 返回值或超时，下载失败时返回错误
+    fn wait_until<R>(
+        &self,
+        timeout: Duration,
+        ready: impl Fn(&ProgressState) -> Option<R>,
+    ) -> Result<R, String> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(result) = ready(&state) {
+                return Ok(result);
+            }
+            if let Some(e) = &state.error {
+                return Err(e.clone());
+            }
+            if state.finished || self.is_cancelled() {
+                return Err("下载已结束".into());
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err("等待数据超时".into());
+            }
+            state = self.changed.wait_timeout(state, deadline - now).unwrap().0;
+        }
+    }
+
+    /// 当前已下载的长度
+    pub fn downloaded(&self) -> u64 {
+        self.state.lock().unwrap().downloaded
+    }
+
+    /// 已知的文件总长度
+    pub fn total(&self) -> Option<u64> {
+        self.state.lock().unwrap().total
+    }
+
+    /// 文件总长度，响应未声明长度时需等待下载完成
+    pub fn wait_total(&self, timeout: Duration) -> Result<u64, String> {
+        self.wait_until(timeout, |state| state.total)
+    }
+
+    /// 等待至少 `min` 字节可读，返回当前已下载的长度
+    pub fn wait_available(&self, min: u64, timeout: Duration) -> Result<u64, String> {
+        self.wait_until(timeout, |state| {
+            (state.downloaded >= min || (state.finished && state.error.is_none()))
+                .then_some(state.downloaded)
+        })
+    }
+}
+
+/// 返回给播放器的响应，头部已包含 Content-Type、Content-Length 等
+pub struct StreamReply {
+    pub status: u16,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Vec<u8>,
+}
+
+/// 一个下载名额，释放时归还；不借用队列，可以随下载任务移入其他线程
+pub struct PreloadGuard {
+    active: Arc<AtomicUsize>,
+}
+
+impl PreloadGuard {
+    /// 进行中的任务数未达 `limit` 时占用一个名额
+    pub fn try_new(active: &Arc<AtomicUsize>, limit: usize) -> Option<Self> {
+        active
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < limit).then_some(n + 1)
+            })
+            .ok()
+            .map(|_| Self {
+                active: active.clone(),
+            })
+    }
+}
+
+impl Drop for PreloadGuard {
+    fn drop(&mut self) {
+        self.active.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// 条目入队后的回调，参数为入队的条目与入队后的队列长度
+type QueuedHook = Box<dyn FnOnce(QueuedVideo, usize) + Send>;
+
+/// 视频预加载的名额；入队前也计入队列深度，避免并发下载超出深度
+pub struct PreloadSlot {
+    _guard: PreloadGuard,
+    starting: Arc<AtomicUsize>,
+    queued: bool,
+    on_queued: Option<QueuedHook>,
+}
+
+impl PreloadSlot {
+    /// 入队成功时调用 `hook`，此时下载可能仍在进行
+    pub fn on_queued(&mut self, hook: impl FnOnce(QueuedVideo, usize) + Send + 'static) {
+        self.on_queued = Some(Box::new(hook));
+    }
+
+    fn mark_queued(&mut self) {
+        if !self.queued {
+            self.queued = true;
+            self.starting.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Drop for PreloadSlot {
+    fn drop(&mut self) {
+        self.mark_queued();
+    }
+}
+
+/// 预加载队列与当前正在播放的条目
+pub struct PreloadQueue {
+    queue: Mutex<VecDeque<PreloadedVideo>>,
+    playing: Mutex<Option<PreloadedVideo>>,
+    /// 进行中的下载数
+    active: Arc<AtomicUsize>,
+    /// 已开始但尚未入队的下载数
+    starting: Arc<AtomicUsize>,
+    depth: AtomicUsize,
+    concurrency: AtomicUsize,
+    /// 允许入队的接口 id，`None` 表示不限制
+    endpoints: Mutex<Option<HashSet<String>>>,
+    cache: DiskCache,
+}
+
+impl PreloadQueue {
+    pub fn new(cache: DiskCache) -> Self {
+        let settings = PreloadSettings::default();
+        Self {
+            queue: Mutex::new(VecDeque::new()),
+            playing: Mutex::new(None),
+            active: Arc::new(AtomicUsize::new(0)),
+            starting: Arc::new(AtomicUsize::new(0)),
+            depth: AtomicUsize::new(settings.video_depth),
+            concurrency: AtomicUsize::new(settings.concurrency),
+            endpoints: Mutex::new(None),
+            cache,
+        }
+    }
+
+    /// 调小深度时多出的条目保留到被取出为止
+    pub fn configure(&self, depth: usize, concurrency: usize) {
+        self.depth.store(depth, Ordering::SeqCst);
+        self.concurrency.store(concurrency, Ordering::SeqCst);
+    }
+
+    pub fn cache(&self) -> &DiskCache {
+        &self.cache
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 申请一个下载名额：受并发数、队列深度与缓存容量限制，进行中的下载已写入的部分也计入容量
+    pub fn try_begin(&self) -> Option<PreloadSlot> {
+        self.evict();
+        if self.cache.total_len() + self.cache.writing_len() >= self.cache.budget() {
+            return None;
+        }
+
+        let queue = self.queue.lock().unwrap();
+        let depth = self.depth.load(Ordering::SeqCst);
+        if queue.len() + self.starting.load(Ordering::SeqCst) >= depth {
+            return None;
+        }
+        let guard = PreloadGuard::try_new(&self.active, self.concurrency.load(Ordering::SeqCst))?;
+        self.starting.fetch_add(1, Ordering::SeqCst);
+        Some(PreloadSlot {
+            _guard: guard,
+            starting: self.starting.clone(),
+            queued: false,
+            on_queued: None,
+        })
+    }
+
+    /// 队列已满或来源的接口已不在当前分类中时丢弃
+    pub fn push(&self, video: PreloadedVideo, slot: &mut PreloadSlot) -> Result<(), String> {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.len() >= self.depth.load(Ordering::SeqCst) {
+            return Err("预加载队列已满".into());
+        }
+        let allowed = self.endpoints.lock().unwrap();
+        if !accepts(&allowed, &video.source.endpoint) {
+            return Err("分类已切换，丢弃旧分类的预加载".into());
+        }
+        let queued = QueuedVideo {
+            id: video.id.clone(),
+            url: video.url.clone(),
+        };
+        queue.push_back(video);
+        let queue_len = queue.len();
+        drop(allowed);
+        drop(queue);
+
+        slot.mark_queued();
+        if let Some(hook) = slot.on_queued.take() {
+            hook(queued, queue_len);
+        }
+        Ok(())
+    }
+
+    /// 来自 `endpoint` 的预加载是否还能入队
+    pub fn accepts(&self, endpoint: &str) -> bool {
+        accepts(&self.endpoints.lock().unwrap(), endpoint)
+    }
+
+    /// 只保留来自 `endpoints` 的条目，其余条目取消下载并移出队列；之后其他接口的预加载不再入队
+    ///
+    /// 正在播放与固定的条目不受影响。返回移除的条目数。
+    pub fn retain_endpoints(&self, endpoints: HashSet<String>) -> usize {
+        let mut queue = self.queue.lock().unwrap();
+        let before = queue.len();
+        queue.retain(|video| {
+            let keep = video.pinned || endpoints.contains(&video.source.endpoint);
+            if let (false, PreloadedMedia::File { progress, .. }) = (keep, &video.media) {
+                progress.cancel();
+            }
+            keep
+        });
+        *self.endpoints.lock().unwrap() = Some(endpoints);
+        before - queue.len()
+    }
+
+    /// 队列中的全部条目，按播放顺序排列
+    pub fn entries(&self) -> Vec<QueueEntry> {
+        let queue = self.queue.lock().unwrap();
+        queue
+            .iter()
+            .map(|video| {
+                let mut entry = QueueEntry {
+                    id: video.id.clone(),
+                    url: video.url.clone(),
+                    category: video.source.category.clone(),
+                    endpoint: video.source.endpoint.clone(),
+                    endpoint_name: video.source.endpoint_name.clone(),
+                    content_type: HLS_CONTENT_TYPE.to_string(),
+                    size: None,
+                    downloaded: 0,
+                    finished: true,
+                    error: None,
+                    pinned: video.pinned,
+                };
+                if let PreloadedMedia::File {
+                    content_type,
+                    progress,
+                    ..
+                } = &video.media
+                {
+                    let state = progress.state.lock().unwrap();
+                    entry.content_type = content_type.clone();
+                    entry.size = state.total;
+                    entry.downloaded = state.downloaded;
+                    entry.finished = state.finished;
+                    entry.error = state.error.clone();
+                }
+                entry
+            })
+            .collect()
+    }
+
+    /// 从队列中移除尚未播放的条目并取消其下载，条目不存在时返回 `false`
+    pub fn remove(&self, id: &str) -> bool {
+        let mut queue = self.queue.lock().unwrap();
+        let index = queue.iter().position(|v| v.id == id);
+        let Some(video) = index.and_then(|index| queue.remove(index)) else {
+            return false;
+        };
+        if let PreloadedMedia::File { progress, .. } = &video.media {
+            progress.cancel();
+        }
+        true
+    }
+
+    /// 把条目移到队首，下一次出队时播放
+    pub fn move_to_front(&self, id: &str) -> bool {
+        let mut queue = self.queue.lock().unwrap();
+        let index = queue.iter().position(|v| v.id == id);
+        let Some(video) = index.and_then(|index| queue.remove(index)) else {
+            return false;
+        };
+        queue.push_front(video);
+        true
+    }
+
+    pub fn set_pinned(&self, id: &str, pinned: bool) -> bool {
+        let mut queue = self.queue.lock().unwrap();
+        match queue.iter_mut().find(|v| v.id == id) {
+            Some(video) => {
+                video.pinned = pinned;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 按容量淘汰不再需要的缓存文件
+    pub fn evict(&self) {
+        self.cache.evict(&self.pinned_keys());
+    }
+
+    /// 队列中与正在播放的文件不能被淘汰
+    fn pinned_keys(&self) -> HashSet<String> {
+        let queue = self.queue.lock().unwrap();
+        let playing = self.playing.lock().unwrap();
+        let mut keys = HashSet::new();
+        for video in queue.iter().chain(playing.iter()) {
+            match &video.media {
+                PreloadedMedia::File { key, .. } => {
+                    keys.insert(key.clone());
+                }
+                PreloadedMedia::Hls(session) => keys.extend(
+                    session
+                        .leading_resources(HLS_PRELOAD_SEGMENTS)
+                        .iter()
+                        .map(|url| DiskCache::key_for(url)),
+                ),
+            }
+        }
+        keys
+    }
+
+    /// 把队列中各条目的地址与指纹加入 `seen`
+    pub fn note_queued(&self, seen: &mut SeenMedia) {
+        for video in self.queue.lock().unwrap().iter() {
+            seen.insert(&video.url, video.fingerprint.as_deref());
+        }
+    }
+
+    /// 取出队首作为正在播放的条目，返回其 id、原始地址、来源与 HLS 会话
+    pub fn pop_next(&self) -> Option<PoppedVideo> {
+        let video = self.queue.lock().unwrap().pop_front()?;
+        let hls = match &video.media {
+            PreloadedMedia::Hls(session) => Some(session.clone()),
+            PreloadedMedia::File { .. } => None,
+        };
+        let result = PoppedVideo {
+            id: video.id.clone(),
+            url: video.url.clone(),
+            source: video.source.clone(),
+            fingerprint: video.fingerprint.clone(),
+            hls,
+        };
+        *self.playing.lock().unwrap() = Some(video);
+        Some(result)
+    }
+
+    /// 直接播放缓存中已有的条目（如回看播放记录），替换当前播放的条目
+    pub fn set_playing(&self, video: PreloadedVideo) {
+        *self.playing.lock().unwrap() = Some(video);
+    }
+
+    /// 按 `Range` / `If-Range` 读取正在播放的条目，不是当前播放的 id 时返回 `None`
+    ///
+    /// 仍在下载时单个区间只返回已写入的部分，请求的起点尚未下载到则最多等待 `timeout`。
+    pub fn read_playing(
+        &self,
+        id: &str,
+        range: Option<&str>,
+        if_range: Option<&str>,
+        timeout: Duration,
+    ) -> Option<Result<StreamReply, String>> {
+        let (key, path, content_type, progress) = match self.playing.lock().unwrap().as_ref() {
+            Some(PreloadedVideo {
+                id: playing_id,
+                media:
+                    PreloadedMedia::File {
+                        key,
+                        path,
+                        content_type,
+                        progress,
+                    },
+                ..
+            }) if playing_id == id => (
+                key.clone(),
+                path.clone(),
+                content_type.clone(),
+                progress.clone(),
+            ),
+            _ => return None,
+        };
+        Some(read_progressive(
+            &key,
+            &path,
+            &content_type,
+            &progress,
+            range,
+            if_range,
+            timeout,
+        ))
+    }
+
+    /// 正在播放的 HLS 会话
+    pub fn playing_hls(&self, id: &str) -> Option<Arc<HlsSession>> {
+        match self.playing.lock().unwrap().as_ref()?.media {
+            PreloadedMedia::Hls(ref session) if session.id == id => Some(session.clone()),
+            _ => None,
+        }
+    }
+
+    /// 清空队列并删除全部缓存文件，进行中的下载会被取消
+    pub fn clear(&self) {
+        let mut queue = self.queue.lock().unwrap();
+        let mut playing = self.playing.lock().unwrap();
+        for video in queue.iter().chain(playing.iter()) {
+            if let PreloadedMedia::File { progress, .. } = &video.media {
+                progress.cancel();
+            }
+        }
+        queue.clear();
+        *playing = None;
+        drop(playing);
+        drop(queue);
+        self.cache.clear();
+    }
+}
+
+/// 还没设置接口范围时全部接受
+fn accepts(allowed: &Option<HashSet<String>>, endpoint: &str) -> bool {
+    allowed
+        .as_ref()
+        .is_none_or(|endpoints| endpoints.contains(endpoint))
+}
+
+/// 预加载的图片，内容已下载到图片缓存；出队时才登记到 `stream:///image/{id}`
+pub struct PreloadedImage {
+    pub image: RemoteImage,
+    pub key: String,
+}
+
+/// 图片预加载队列，同时进行的下载数与视频共用同一设置
+pub struct ImagePreloadQueue {
+    queue: Mutex<VecDeque<PreloadedImage>>,
+    depth: AtomicUsize,
+    concurrency: AtomicUsize,
+    /// 进行中的下载数
+    active: Arc<AtomicUsize>,
+    /// 每次清空加一，清空前开始的预加载结果不再入队
+    generation: AtomicU64,
+}
+
+impl Default for ImagePreloadQueue {
+    fn default() -> Self {
+        let settings = PreloadSettings::default();
+        Self::new(settings.image_depth, settings.concurrency)
+    }
+}
+
+impl ImagePreloadQueue {
+    pub fn new(depth: usize, concurrency: usize) -> Self {
+        Self {
+            queue: Mutex::new(VecDeque::new()),
+            depth: AtomicUsize::new(depth),
+            concurrency: AtomicUsize::new(concurrency),
+            active: Arc::new(AtomicUsize::new(0)),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::SeqCst)
+    }
+
+    pub fn configure(&self, depth: usize, concurrency: usize) {
+        self.depth.store(depth, Ordering::SeqCst);
+        self.concurrency.store(concurrency, Ordering::SeqCst);
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 申请一个下载名额，进行中的下载也计入队列深度
+    pub fn try_begin(&self) -> Option<PreloadGuard> {
+        let queue = self.queue.lock().unwrap();
+        if queue.len() + self.active.load(Ordering::SeqCst) >= self.depth() {
+            return None;
+        }
+        PreloadGuard::try_new(&self.active, self.concurrency.load(Ordering::SeqCst))
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// 队列已满或期间被清空过时丢弃并返回 `false`
+    pub fn push(&self, image: PreloadedImage, generation: u64) -> bool {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.len() >= self.depth() || generation != self.generation() {
+            return false;
+        }
+        queue.push_back(image);
+        true
+    }
+
+    pub fn pop(&self) -> Option<PreloadedImage> {
+        self.queue.lock().unwrap().pop_front()
+    }
+
+    /// 下一张图片的原始地址
+    pub fn peek(&self) -> Option<String> {
+        self.queue
+            .lock()
+            .unwrap()
+            .front()
+            .map(|image| image.image.url.clone())
+    }
+
+    /// 队列中的图片不能被淘汰
+    pub fn pinned_keys(&self) -> HashSet<String> {
+        self.queue
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|image| image.key.clone())
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        let mut queue = self.queue.lock().unwrap();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        queue.clear();
+    }
+}
+
+/// 读取长度未知、仍在下载的文件，从 `range`（默认从头）起返回最多一个窗口的已下载数据，
+/// 以 `bytes start-end/*` 标明总长度未知；没有校验值，不处理 `If-Range`
+fn read_growing(
+    path: &Path,
+    content_type: &str,
+    progress: &DownloadProgress,
+    range: Option<(u64, Option<u64>)>,
+    timeout: Duration,
+) -> Result<StreamReply, String> {
+    let (start, end) = range.unwrap_or((0, None));
+    let available = progress.wait_available(start + 1, timeout)?;
+    if available <= start {
+        // 下载已结束，此时长度已知
+        let total = progress.total().unwrap_or(available);
+        return Ok(StreamReply {
+            status: 416,
+            headers: vec![
+                ("Accept-Ranges", "bytes".to_string()),
+                ("Content-Range", unsatisfied_range(total)),
+                ("Content-Length", "0".to_string()),
+            ],
+            body: Vec::new(),
+        });
+    }
+
+    let window_end = (available - 1).min(start + PROXY_WINDOW - 1);
+    let end = end.map_or(window_end, |end| end.min(window_end));
+    let body = read_file_range(path, start, end - start + 1)
+        .map_err(|e| format!("读取缓存失败: {}", e))?;
+    let total = progress
+        .total()
+        .map_or("*".to_string(), |total| total.to_string());
+    Ok(StreamReply {
+        status: 206,
+        headers: vec![
+            ("Accept-Ranges", "bytes".to_string()),
+            ("Content-Type", content_type.to_string()),
+            (
+                "Content-Range",
+                format!("bytes {}-{}/{}", start, end, total),
+            ),
+            ("Content-Length", body.len().to_string()),
+        ],
+        body,
+    })
+}
+
+/// 按 `Range` / `If-Range` 读取缓存中的完整文件
+pub fn read_cached(
+    file: &CachedFile,
+    content_type: &str,
+    range: Option<&str>,
+    if_range: Option<&str>,
+) -> Result<StreamReply, String> {
+    let progress = DownloadProgress::completed(file.len);
+    read_progressive(
+        &file.key,
+        &file.path,
+        content_type,
+        &progress,
+        range,
+        if_range,
+        Duration::ZERO,
+    )
+}
+
+fn read_progressive(
+    key: &str,
+    path: &Path,
+    content_type: &str,
+    progress: &DownloadProgress,
+    range: Option<&str>,
+    if_range: Option<&str>,
+    timeout: Duration,
+) -> Result<StreamReply, String> {
+    // 长度未知时只有后缀区间需要等下载完成，其余请求先返回已下载的部分
+    let specs = range.and_then(parse_range_header);
+    let total = match progress.total() {
+        Some(total) => total,
+        None if specs
+            .iter()
+            .flatten()
+            .any(|spec| matches!(spec, ByteRangeSpec::Suffix(_))) =>
+        {
+            progress.wait_total(timeout)?
+        }
+        None => {
+            let start = specs
+                .iter()
+                .flatten()
+                .filter_map(|spec| match spec {
+                    ByteRangeSpec::From { start, end } => Some((*start, *end)),
+                    ByteRangeSpec::Suffix(_) => None,
+                })
+                .min();
+            return read_growing(path, content_type, progress, start, timeout);
+        }
+    };
+    let etag = format!("\"{}-{}\"", key, total);
+    let read = |start: u64, end: u64| {
+        read_file_range(path, start, end - start + 1).map_err(|e| format!("读取缓存失败: {}", e))
+    };
+
+    let mut headers = vec![
+        ("Accept-Ranges", "bytes".to_string()),
+        ("ETag", etag.clone()),
+    ];
+    // 与远程代理一致，单次最多返回一个窗口，较大的文件由播放器按区间继续请求
+    let request = match evaluate(range, if_range, &etag, total) {
+        RangeRequest::Full if total > PROXY_WINDOW => RangeRequest::Ranges(vec![(0, total - 1)]),
+        // 多区间合计超过一个窗口或还没下载完时，只以单区间回应第一个区间
+        RangeRequest::Ranges(ranges)
+            if ranges.len() > 1
+                && (ranges
+                    .iter()
+                    .map(|(start, end)| end - start + 1)
+                    .sum::<u64>()
+                    > PROXY_WINDOW
+                    || ranges.iter().any(|&(_, end)| end >= progress.downloaded())) =>
+        {
+            RangeRequest::Ranges(vec![ranges[0]])
+        }
+        request => request,
+    };
+    let (status, body) = match request {
+        RangeRequest::Unsatisfiable => {
+            headers.push(("Content-Range", unsatisfied_range(total)));
+            (416, Vec::new())
+        }
+        RangeRequest::Full => {
+            progress.wait_available(total, timeout)?;
+            headers.push(("Content-Type", content_type.to_string()));
+            let body = if total == 0 {
+                Vec::new()
+            } else {
+                read(0, total - 1)?
+            };
+            (200, body)
+        }
+        RangeRequest::Ranges(ranges) if ranges.len() == 1 => {
+            let (start, end) = ranges[0];
+            // 只返回已下载的部分，播放器会继续请求后面的数据
+            let available = progress.wait_available(start + 1, timeout)?;
+            if available <= start {
+                return Err("请求范围超出已下载的数据".into());
+            }
+            let end = end.min(available - 1).min(start + PROXY_WINDOW - 1);
+            headers.push(("Content-Type", content_type.to_string()));
+            headers.push(("Content-Range", content_range(start, end, total)));
+            (206, read(start, end)?)
+        }
+        RangeRequest::Ranges(ranges) => {
+            let parts = ranges
+                .into_iter()
+                .map(|(start, end)| Ok((start, end, read(start, end)?)))
+                .collect::<Result<Vec<_>, String>>()?;
+            let (content_type, body) = multipart_body(&parts, content_type, total);
+            headers.push(("Content-Type", content_type));
+            (206, body)
+        }
+    };
+
+    headers.push(("Content-Length", body.len().to_string()));
+    Ok(StreamReply {
+        status,
+        headers,
+        body,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::MB;
+    use std::fs;
+    use uuid::Uuid;
+
+    fn temp_file(len: usize) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("preload-test-{}.bin", Uuid::new_v4()));
+        let data: Vec<u8> = (0..len).map(|i| i as u8).collect();
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    fn header<'a>(reply: &'a StreamReply, name: &str) -> Option<&'a str> {
+        reply
+            .headers
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn caps_multi_range_replies() {
+        let len = 3 * MB;
+        let path = temp_file(len as usize);
+        let progress = DownloadProgress::completed(len);
+        let read = |range: &str| {
+            read_progressive(
+                "k",
+                &path,
+                "video/mp4",
+                &progress,
+                Some(range),
+                None,
+                Duration::ZERO,
+            )
+            .unwrap()
+        };
+
+        let small = read("bytes=0-9,100-199");
+        assert_eq!(small.status, 206);
+        assert!(header(&small, "Content-Type")
+            .unwrap()
+            .starts_with("multipart/byteranges"));
+
+        let large = read("bytes=0-1999999,2500000-2999999");
+        assert_eq!(large.status, 206);
+        assert_eq!(
+            header(&large, "Content-Range"),
+            Some("bytes 0-1999999/3145728")
+        );
+        assert_eq!(large.body.len(), 2_000_000);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn multi_range_does_not_wait_for_later_ranges() {
+        let path = temp_file(1000);
+        let progress = DownloadProgress::new(Some(1000));
+        progress.advance(50);
+        let reply = read_progressive(
+            "k",
+            &path,
+            "video/mp4",
+            &progress,
+            Some("bytes=0-9,500-599"),
+            None,
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        assert_eq!(header(&reply, "Content-Range"), Some("bytes 0-9/1000"));
+        assert_eq!(reply.body, (0..10).collect::<Vec<u8>>());
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn reads_growing_file_with_unknown_length() {
+        let path = temp_file(1000);
+        let progress = DownloadProgress::new(None);
+        progress.advance(100);
+        let read = |range| {
+            read_progressive(
+                "k",
+                &path,
+                "video/mp4",
+                &progress,
+                range,
+                None,
+                Duration::ZERO,
+            )
+            .unwrap()
+        };
+
+        let reply = read(Some("bytes=10-49"));
+        assert_eq!(reply.status, 206);
+        assert_eq!(header(&reply, "Content-Range"), Some("bytes 10-49/*"));
+        assert_eq!(reply.body, (10..50).collect::<Vec<u8>>());
+
+        let reply = read(None);
+        assert_eq!(header(&reply, "Content-Range"), Some("bytes 0-99/*"));
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn waits_for_data_until_timeout() {
+        let path = temp_file(1000);
+        let progress = Arc::new(DownloadProgress::new(Some(1000)));
+        progress.advance(10);
+        let writer = progress.clone();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            writer.advance(200);
+        });
+        let read = |range, timeout| {
+            read_progressive(
+                "k",
+                &path,
+                "video/mp4",
+                &progress,
+                Some(range),
+                None,
+                timeout,
+            )
+        };
+
+        let reply = read("bytes=100-", Duration::from_secs(5)).unwrap();
+        assert_eq!(header(&reply, "Content-Range"), Some("bytes 100-199/1000"));
+        handle.join().unwrap();
+
+        let err = read("bytes=500-", Duration::from_millis(20)).err().unwrap();
+        assert_eq!(err, "等待数据超时");
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn answers_416_after_download_finished() {
+        let path = temp_file(100);
+        let progress = DownloadProgress::new(None);
+        progress.advance(100);
+        progress.finish(Ok(100));
+
+        let reply = read_growing(
+            &path,
+            "video/mp4",
+            &progress,
+            Some((200, None)),
+            Duration::ZERO,
+        )
+        .unwrap();
+        assert_eq!(reply.status, 416);
+        assert_eq!(header(&reply, "Content-Range"), Some("bytes */100"));
+
+        let reply = read_progressive(
+            "k",
+            &path,
+            "video/mp4",
+            &progress,
+            Some("bytes=200-"),
+            None,
+            Duration::ZERO,
+        )
+        .unwrap();
+        assert_eq!(reply.status, 416);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn caps_single_range_and_full_reads_to_window() {
+        let len = 3 * MB;
+        let path = temp_file(len as usize);
+        let progress = DownloadProgress::completed(len);
+        let expected = format!("bytes 0-{}/{}", PROXY_WINDOW - 1, len);
+        for range in [Some("bytes=0-"), None] {
+            let reply = read_progressive(
+                "k",
+                &path,
+                "video/mp4",
+                &progress,
+                range,
+                None,
+                Duration::ZERO,
+            )
+            .unwrap();
+            assert_eq!(reply.status, 206);
+            assert_eq!(header(&reply, "Content-Range"), Some(expected.as_str()));
+            assert_eq!(reply.body.len() as u64, PROXY_WINDOW);
+        }
+        let _ = fs::remove_file(path);
+    }
+
+    fn video(id: &str, endpoint: &str) -> PreloadedVideo {
+        PreloadedVideo {
+            id: id.into(),
+            url: format!("https://example.com/{}.mp4", id),
+            source: PreloadSource {
+                category: "c".into(),
+                endpoint: endpoint.into(),
+                endpoint_name: endpoint.into(),
+            },
+            media: PreloadedMedia::File {
+                key: id.into(),
+                path: PathBuf::from(id),
+                content_type: "video/mp4".into(),
+                progress: Arc::new(DownloadProgress::new(None)),
+            },
+            pinned: false,
+            fingerprint: None,
+        }
+    }
+
+    fn ids(queue: &PreloadQueue) -> Vec<String> {
+        queue.entries().into_iter().map(|entry| entry.id).collect()
+    }
+
+    #[test]
+    fn manipulates_queue_entries() {
+        let dir = std::env::temp_dir().join(format!("preload-queue-{}", Uuid::new_v4()));
+        let queue = PreloadQueue::new(DiskCache::open(dir, MB));
+        queue.configure(3, 3);
+        let push = |video| queue.push(video, &mut queue.try_begin().unwrap());
+
+        push(video("a", "e2")).unwrap();
+        push(video("b", "e1")).unwrap();
+        push(video("c", "e2")).unwrap();
+        assert!(queue.try_begin().is_none());
+        assert_eq!(ids(&queue), ["a", "b", "c"]);
+
+        assert!(queue.move_to_front("c"));
+        assert!(!queue.move_to_front("x"));
+        assert_eq!(ids(&queue), ["c", "a", "b"]);
+
+        assert!(queue.set_pinned("a", true));
+        assert!(!queue.set_pinned("x", true));
+        let removed = queue.retain_endpoints(HashSet::from(["e1".to_string()]));
+        assert_eq!(removed, 1);
+        assert_eq!(ids(&queue), ["a", "b"]);
+
+        let err = push(video("d", "e2")).unwrap_err();
+        assert_eq!(err, "分类已切换，丢弃旧分类的预加载");
+        assert!(queue.accepts("e1") && !queue.accepts("e2"));
+        push(video("e", "e1")).unwrap();
+        assert_eq!(ids(&queue), ["a", "b", "e"]);
+    }
+}
@@ -0,0 +1,278 @@
+use reqwest::header::{ACCEPT_RANGES, CONTENT_RANGE, CONTENT_TYPE, IF_RANGE, RANGE};
+use reqwest::StatusCode;
+
+use crate::media::MB;
+use crate::preload::StreamReply;
+use crate::range::{parse_range_header, unsatisfied_range, ByteRangeSpec};
+
+/// 单次代理响应最多携带的字节数，更大的请求会被截成多个窗口
+pub const PROXY_WINDOW: u64 = 2 * MB;
+
+/// 代理给 WebView 的一个响应窗口
+pub struct ProxyResponse {
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub content_range: Option<String>,
+    pub data: Vec<u8>,
+}
+
+impl ProxyResponse {
+    /// 转为返回给播放器的响应，`content_type` 由调用方按文件头等确定
+    pub fn into_reply(self, content_type: String) -> StreamReply {
+        let mut headers = vec![
+            ("Content-Type", content_type),
+            ("Accept-Ranges", "bytes".to_string()),
+            ("Content-Length", self.data.len().to_string()),
+        ];
+        if let Some(range) = self.content_range {
+            headers.push(("Content-Range", range));
+        }
+        StreamReply {
+            status: self.status,
+            headers,
+            body: self.data,
+        }
+    }
+}
+
+/// 解析上游的 `bytes start-end/total`，返回起点与总长度（`*` 为未知）
+fn parse_content_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (range, total) = value.trim().strip_prefix("bytes ")?.split_once('/')?;
+    let start = range.split_once('-')?.0.trim().parse().ok()?;
+    Some((start, total.trim().parse().ok()))
+}
+
+/// 读取正文，先跳过 `skip` 字节，最多保留 `limit` 字节；返回数据与是否读到结尾
+async fn read_window(
+    resp: &mut reqwest::Response,
+    mut skip: u64,
+    limit: u64,
+) -> Result<(Vec<u8>, bool), String> {
+    let mut data = Vec::with_capacity(limit.min(PROXY_WINDOW) as usize);
+    loop {
+        let chunk = match resp.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => return Ok((data, true)),
+            Err(e) => return Err(format!("读取上游失败: {}", e)),
+        };
+
+        if take_chunk(&mut data, &chunk, &mut skip, limit) {
+            return Ok((data, false));
+        }
+    }
+}
+
+/// 把一个数据块中跳过 `skip` 之后的部分追加到 `data`，最多到 `limit` 字节；返回窗口是否已满
+fn take_chunk(data: &mut Vec<u8>, mut chunk: &[u8], skip: &mut u64, limit: u64) -> bool {
+    if *skip > 0 {
+        let n = (*skip).min(chunk.len() as u64) as usize;
+        *skip -= n as u64;
+        chunk = &chunk[n..];
+    }
+
+    let room = (limit - data.len() as u64) as usize;
+    data.extend_from_slice(&chunk[..chunk.len().min(room)]);
+    data.len() as u64 >= limit
+}
+
+/// 上游可能返回比请求更大的区间，按实际截取的 `len` 字节重写 `Content-Range`
+fn rewrite_content_range(upstream: Option<String>, len: usize) -> Option<String> {
+    match upstream.as_deref().and_then(parse_content_range) {
+        Some((first, total)) if len > 0 => Some(format!(
+            "bytes {}-{}/{}",
+            first,
+            first + len as u64 - 1,
+            total.map_or("*".to_string(), |t| t.to_string())
+        )),
+        _ => upstream,
+    }
+}
+
+/// 按窗口代理远程视频：只向上游请求 `PROXY_WINDOW` 大小的区间并边读边截断，
+/// 内存占用与文件大小无关，播放器会继续请求后面的区间。
+///
+/// 后缀区间按上游返回的区间截取一个窗口；多区间只回应第一个区间，单部分的 206 同样合法。
+/// 语法无效的 Range 按规范忽略。
+/// `If-Range` 会一并转发，上游因校验不匹配返回完整内容时从头开始发送。
+/// 没有 Range 时也只返回第一个窗口，以带 `Content-Range` 的 206 回应（RFC 9110 允许），
+/// 单个响应的内存占用始终不超过一个窗口。
+pub async fn fetch_window(
+    req: reqwest::RequestBuilder,
+    range: Option<&str>,
+    if_range: Option<&str>,
+) -> Result<ProxyResponse, String> {
+    let specs = range.and_then(parse_range_header);
+    let range = specs.as_ref().and(range);
+    let req = match if_range {
+        Some(if_range) if range.is_some() => req.header(IF_RANGE, if_range),
+        _ => req,
+    };
+
+    // 没有 Range 时按 `bytes=0-` 处理，避免一次读完整个文件
+    let first = specs
+        .as_deref()
+        .and_then(|specs| specs.first().copied())
+        .unwrap_or(ByteRangeSpec::From {
+            start: 0,
+            end: None,
+        });
+    let (header, start, suffix, limit) = match first {
+        ByteRangeSpec::From { start, end } => {
+            let window_end = start + PROXY_WINDOW - 1;
+            let window_end = end.map_or(window_end, |end| end.min(window_end));
+            let header = format!("bytes={}-{}", start, window_end);
+            (header, start, None, window_end - start + 1)
+        }
+        // 起点要等上游给出总长度才知道
+        ByteRangeSpec::Suffix(len) => (
+            format!("bytes=-{}", len),
+            0,
+            Some(len),
+            len.min(PROXY_WINDOW),
+        ),
+    };
+    let mut resp = req
+        .header(RANGE, header)
+        .send()
+        .await
+        .map_err(|e| format!("请求失败: {}", e))?;
+
+    let status = resp.status();
+    let content_type = header_string(&resp, CONTENT_TYPE);
+
+    match status {
+        StatusCode::PARTIAL_CONTENT => {
+            let upstream_range = header_string(&resp, CONTENT_RANGE);
+            let (data, _) = read_window(&mut resp, 0, limit).await?;
+            let content_range = rewrite_content_range(upstream_range, data.len());
+            // 未带 Range 且一个窗口就是整个文件时按普通响应返回
+            let whole = content_range
+                .as_deref()
+                .and_then(parse_content_range)
+                .is_some_and(|(first, total)| first == 0 && total == Some(data.len() as u64));
+            if range.is_none() && whole {
+                return Ok(ProxyResponse {
+                    status: 200,
+                    content_type,
+                    content_range: None,
+                    data,
+                });
+            }
+            Ok(ProxyResponse {
+                status: status.as_u16(),
+                content_type,
+                content_range,
+                data,
+            })
+        }
+        // 上游不支持 Range，或 If-Range 不匹配：跳过前面的数据，只保留本窗口
+        StatusCode::OK => {
+            let total = resp.content_length();
+            // 后缀区间按总长度换算起点，长度未知时只能从头发送
+            let start = match suffix {
+                Some(len) => total.map(|total| total.saturating_sub(len)),
+                None => Some(start),
+            };
+            let fresh = start.is_none()
+                || range.is_none()
+                || (if_range.is_some() && resp_accepts_ranges(&resp));
+            let start = start.filter(|_| !fresh).unwrap_or(0);
+            let limit = if fresh { PROXY_WINDOW } else { limit };
+            let (data, eof) = read_window(&mut resp, start, limit).await?;
+
+            if fresh && eof {
+                return Ok(ProxyResponse {
+                    status: 200,
+                    content_type,
+                    content_range: None,
+                    data,
+                });
+            }
+            if data.is_empty() {
+                return Ok(ProxyResponse {
+                    status: 416,
+                    content_type: None,
+                    content_range: total.map(unsatisfied_range),
+                    data,
+                });
+            }
+
+            let total = total
+                .or_else(|| eof.then_some(start + data.len() as u64))
+                .map_or("*".to_string(), |t| t.to_string());
+            Ok(ProxyResponse {
+                status: 206,
+                content_type,
+                content_range: Some(format!(
+                    "bytes {}-{}/{}",
+                    start,
+                    start + data.len() as u64 - 1,
+                    total
+                )),
+                data,
+            })
+        }
+        _ => {
+            let content_range = header_string(&resp, CONTENT_RANGE);
+            let (data, _) = read_window(&mut resp, 0, PROXY_WINDOW).await?;
+            Ok(ProxyResponse {
+                status: status.as_u16(),
+                content_type,
+                content_range,
+                data,
+            })
+        }
+    }
+}
+
+fn resp_accepts_ranges(resp: &reqwest::Response) -> bool {
+    header_string(resp, ACCEPT_RANGES).is_some_and(|v| v.eq_ignore_ascii_case("bytes"))
+}
+
+fn header_string(resp: &reqwest::Response, name: reqwest::header::HeaderName) -> Option<String> {
+    resp.headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_upstream_content_range() {
+        assert_eq!(
+            parse_content_range("bytes 0-99/1000"),
+            Some((0, Some(1000)))
+        );
+        assert_eq!(parse_content_range(" bytes 100-199/* "), Some((100, None)));
+        assert_eq!(parse_content_range("bytes */1000"), None);
+        assert_eq!(parse_content_range("items 0-9/10"), None);
+    }
+
+    #[test]
+    fn truncates_chunks_to_window() {
+        let mut data = Vec::new();
+        let mut skip = 3;
+        assert!(!take_chunk(&mut data, b"abcde", &mut skip, 4));
+        assert_eq!((data.as_slice(), skip), (&b"de"[..], 0));
+        assert!(take_chunk(&mut data, b"fghij", &mut skip, 4));
+        assert_eq!(data, b"defg");
+    }
+
+    #[test]
+    fn rewrites_content_range_to_truncated_length() {
+        let rewrite = |value: &str, len| rewrite_content_range(Some(value.to_string()), len);
+        assert_eq!(
+            rewrite("bytes 100-9999/10000", 50).as_deref(),
+            Some("bytes 100-149/10000")
+        );
+        assert_eq!(
+            rewrite("bytes 0-9999/*", 10).as_deref(),
+            Some("bytes 0-9/*")
+        );
+        assert_eq!(rewrite("bytes 0-9/10", 0).as_deref(), Some("bytes 0-9/10"));
+        assert_eq!(rewrite_content_range(None, 10), None);
+    }
+}
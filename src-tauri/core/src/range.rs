@@ -0,0 +1,224 @@
+//! 按 RFC 9110 处理 `Range` / `If-Range` 请求头
+
+/// 单个区间的原始写法
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteRangeSpec {
+    /// `start-end` 或 `start-`
+    From { start: u64, end: Option<u64> },
+    /// `-suffix`，即最后 N 个字节
+    Suffix(u64),
+}
+
+/// 对已知长度的资源求值后的结果
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// 没有 Range、语法无效或 If-Range 不匹配，返回完整内容
+    Full,
+    /// 闭区间，已裁剪到资源长度并合并重叠部分
+    Ranges(Vec<(u64, u64)>),
+    /// 所有区间都不可满足，应返回 416
+    Unsatisfiable,
+}
+
+/// 单个请求允许的最大区间数，超出时视为无效以免被滥用
+const MAX_RANGES: usize = 16;
+
+/// 解析 `bytes=...`，语法无效或单位不是 bytes 时返回 `None`（按规范应忽略该头）
+pub fn parse_range_header(header: &str) -> Option<Vec<ByteRangeSpec>> {
+    let (unit, set) = header.trim().split_once('=')?;
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return None;
+    }
+
+    let mut specs = Vec::new();
+    for part in set.split(',') {
+        let part = part.trim();
+        if part.is_empty() {
+            continue;
+        }
+        let (start, end) = part.split_once('-')?;
+        let (start, end) = (start.trim(), end.trim());
+
+        let spec = if start.is_empty() {
+            ByteRangeSpec::Suffix(parse_digits(end)?)
+        } else {
+            let start = parse_digits(start)?;
+            let end = match end {
+                "" => None,
+                end => Some(parse_digits(end)?),
+            };
+            if end.is_some_and(|end| end < start) {
+                return None;
+            }
+            ByteRangeSpec::From { start, end }
+        };
+        specs.push(spec);
+    }
+
+    if specs.is_empty() || specs.len() > MAX_RANGES {
+        return None;
+    }
+    Some(specs)
+}
+
+fn parse_digits(s: &str) -> Option<u64> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// 按资源长度裁剪区间，丢弃不可满足的部分并合并重叠区间
+pub fn resolve_ranges(specs: &[ByteRangeSpec], total: u64) -> RangeRequest {
+    let mut ranges: Vec<(u64, u64)> = specs
+        .iter()
+        .filter_map(|spec| match *spec {
+            ByteRangeSpec::From { start, end } if start < total => {
+                Some((start, end.map_or(total - 1, |end| end.min(total - 1))))
+            }
+            ByteRangeSpec::Suffix(len) if len > 0 && total > 0 => {
+                Some((total - len.min(total), total - 1))
+            }
+            _ => None,
+        })
+        .collect();
+
+    if ranges.is_empty() {
+        return RangeRequest::Unsatisfiable;
+    }
+    if ranges.len() > 1 {
+        ranges.sort_unstable();
+        let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+        for (start, end) in ranges {
+            match merged.last_mut() {
+                Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        ranges = merged;
+    }
+    RangeRequest::Ranges(ranges)
+}
+
+/// `If-Range` 只接受强校验的 ETag；日期形式因没有 Last-Modified 一律视为不匹配
+pub fn if_range_matches(if_range: &str, etag: &str) -> bool {
+    let if_range = if_range.trim();
+    !if_range.starts_with("W/") && if_range == etag
+}
+
+/// 综合 `Range` 与 `If-Range` 得出应返回的内容
+pub fn evaluate(
+    range: Option<&str>,
+    if_range: Option<&str>,
+    etag: &str,
+    total: u64,
+) -> RangeRequest {
+    let specs = match range.and_then(parse_range_header) {
+        Some(specs) => specs,
+        None => return RangeRequest::Full,
+    };
+    if if_range.is_some_and(|if_range| !if_range_matches(if_range, etag)) {
+        return RangeRequest::Full;
+    }
+    resolve_ranges(&specs, total)
+}
+
+pub fn content_range(start: u64, end: u64, total: u64) -> String {
+    format!("bytes {}-{}/{}", start, end, total)
+}
+
+/// 416 响应使用的 `Content-Range`
+pub fn unsatisfied_range(total: u64) -> String {
+    format!("bytes */{}", total)
+}
+
+/// 生成 `multipart/byteranges` 正文，返回响应的 Content-Type 与正文
+pub fn multipart_body(
+    parts: &[(u64, u64, Vec<u8>)],
+    content_type: &str,
+    total: u64,
+) -> (String, Vec<u8>) {
+    let boundary = format!("video-player-{}", uuid::Uuid::new_v4().simple());
+
+    let mut body = Vec::new();
+    for (start, end, data) in parts {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+                boundary,
+                content_type,
+                content_range(*start, *end, total)
+            )
+            .as_bytes(),
+        );
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+    (format!("multipart/byteranges; boundary={}", boundary), body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(header: &str, total: u64) -> RangeRequest {
+        evaluate(Some(header), None, "\"etag\"", total)
+    }
+
+    #[test]
+    fn suffix_and_open_ended() {
+        assert_eq!(
+            ranges("bytes=-100", 1000),
+            RangeRequest::Ranges(vec![(900, 999)])
+        );
+        assert_eq!(
+            ranges("bytes=-5000", 1000),
+            RangeRequest::Ranges(vec![(0, 999)])
+        );
+        assert_eq!(
+            ranges("bytes=500-", 1000),
+            RangeRequest::Ranges(vec![(500, 999)])
+        );
+        assert_eq!(
+            ranges("bytes=0-99999", 1000),
+            RangeRequest::Ranges(vec![(0, 999)])
+        );
+    }
+
+    #[test]
+    fn multi_range_is_sorted_and_merged() {
+        assert_eq!(
+            ranges("bytes=500-599, 0-99, 90-199, 200-210", 1000),
+            RangeRequest::Ranges(vec![(0, 210), (500, 599)])
+        );
+    }
+
+    #[test]
+    fn unsatisfiable() {
+        assert_eq!(ranges("bytes=1000-", 1000), RangeRequest::Unsatisfiable);
+        assert_eq!(ranges("bytes=-0", 1000), RangeRequest::Unsatisfiable);
+        assert_eq!(ranges("bytes=0-", 0), RangeRequest::Unsatisfiable);
+    }
+
+    #[test]
+    fn invalid_or_mismatched_is_full() {
+        assert_eq!(evaluate(None, None, "\"etag\"", 10), RangeRequest::Full);
+        assert_eq!(ranges("items=0-1", 10), RangeRequest::Full);
+        assert_eq!(ranges("bytes=5-2", 10), RangeRequest::Full);
+        assert_eq!(ranges("bytes=a-b", 10), RangeRequest::Full);
+        assert_eq!(
+            evaluate(Some("bytes=0-1"), Some("\"other\""), "\"etag\"", 10),
+            RangeRequest::Full
+        );
+        assert_eq!(
+            evaluate(Some("bytes=0-1"), Some("W/\"etag\""), "W/\"etag\"", 10),
+            RangeRequest::Full
+        );
+        assert_eq!(
+            evaluate(Some("bytes=0-1"), Some("\"etag\""), "\"etag\"", 10),
+            RangeRequest::Ranges(vec![(0, 1)])
+        );
+    }
+}
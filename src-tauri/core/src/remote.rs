@@ -0,0 +1,133 @@
+//! 经 stream 协议提供的远程媒体会话表：每个解析出的视频、图片按 id 寻址，可同时存在多个
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::hls::HlsSession;
+use crate::media::MediaKind;
+use crate::mime::{normalize_declared, resolve_content_type, SNIFF_LEN};
+use crate::proxy::ProxyResponse;
+
+/// 会话最后一次被请求后保留的时间
+pub const REMOTE_SESSION_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// 同时保留的会话数，超出时淘汰最久未使用的
+const MAX_REMOTE_SESSIONS: usize = 8;
+
+/// 一个经 `stream:///remote/{id}` 代理的远程视频
+pub struct RemoteVideo {
+    pub id: String,
+    pub url: String,
+    /// 地址是 HLS 时对应的播放会话，会话 id 与 `id` 相同
+    pub hls: Option<Arc<HlsSession>>,
+    /// 从文件头识别出的类型，后续窗口不含文件头时沿用
+    sniffed_type: Mutex<Option<String>>,
+}
+
+impl RemoteVideo {
+    pub fn new(id: String, url: String, hls: Option<Arc<HlsSession>>) -> Self {
+        Self {
+            id,
+            url,
+            hls,
+            sniffed_type: Mutex::new(None),
+        }
+    }
+
+    /// 代理响应的 Content-Type：窗口从文件开头起时按文件头识别并记住，其余窗口沿用
+    pub fn content_type(&self, resp: &ProxyResponse) -> String {
+        let from_start = match resp.content_range.as_deref() {
+            Some(range) => range.starts_with("bytes 0-"),
+            None => resp.status == 200,
+        };
+        let fallback = MediaKind::Video.spec().default_content_type;
+        let mut sniffed = self.sniffed_type.lock().unwrap();
+        if from_start && !resp.data.is_empty() {
+            let mime = resolve_content_type(
+                resp.content_type.as_deref(),
+                &resp.data[..resp.data.len().min(SNIFF_LEN)],
+                fallback,
+            );
+            *sniffed = Some(mime.clone());
+            return mime;
+        }
+        sniffed
+            .clone()
+            .or_else(|| normalize_declared(resp.content_type.as_deref()))
+            .unwrap_or_else(|| fallback.to_string())
+    }
+}
+
+/// 经 `stream:///image/{id}` 提供的远程图片，首次请求时下载到磁盘缓存
+pub struct RemoteImage {
+    pub id: String,
+    pub url: String,
+    /// 来源接口，收藏时记录
+    pub endpoint: String,
+    pub endpoint_name: String,
+}
+
+struct Entry<T> {
+    item: Arc<T>,
+    last_used: Instant,
+}
+
+/// 按 id 登记的远程媒体，长时间未被请求的会话自动过期
+pub struct RemoteSessions<T> {
+    entries: Mutex<HashMap<String, Entry<T>>>,
+    ttl: Duration,
+}
+
+impl<T> Default for RemoteSessions<T> {
+    fn default() -> Self {
+        Self::new(REMOTE_SESSION_TTL)
+    }
+}
+
+impl<T> RemoteSessions<T> {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    pub fn register(&self, id: String, item: T) -> Arc<T> {
+        let item = Arc::new(item);
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        self.prune(&mut entries, now);
+        if entries.len() >= MAX_REMOTE_SESSIONS {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(id, _)| id.clone());
+            if let Some(id) = oldest {
+                entries.remove(&id);
+            }
+        }
+        entries.insert(
+            id,
+            Entry {
+                item: item.clone(),
+                last_used: now,
+            },
+        );
+        item
+    }
+
+    /// 查找会话并刷新其过期时间
+    pub fn get(&self, id: &str) -> Option<Arc<T>> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        self.prune(&mut entries, now);
+        let entry = entries.get_mut(id)?;
+        entry.last_used = now;
+        Some(entry.item.clone())
+    }
+
+    fn prune(&self, entries: &mut HashMap<String, Entry<T>>, now: Instant) {
+        entries.retain(|_, entry| now.duration_since(entry.last_used) < self.ttl);
+    }
+}
@@ -0,0 +1,329 @@
+use bytes::Bytes;
+use reqwest::header::{
+    HeaderMap, HeaderValue, ACCEPT, ACCEPT_LANGUAGE, CONTENT_TYPE, LOCATION, REFERER, USER_AGENT,
+};
+use serde_json::Value;
+use std::time::Duration;
+
+use crate::extract::{extract_with_rule, find_media_in_html};
+use crate::hls::{self, is_playlist};
+use crate::media::{MediaKind, MediaSpec, MB};
+use crate::model::{ApiEndpoint, ResponseKind};
+
+pub const BROWSER_UA: &str =
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 Chrome/120.0.0.0";
+
+/// 解析接口用的客户端，跟随重定向以拿到最终地址
+pub fn resolver_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .default_headers({
+            let mut h = HeaderMap::new();
+            h.insert(USER_AGENT, HeaderValue::from_static(BROWSER_UA));
+            h.insert(ACCEPT, HeaderValue::from_static("*/*"));
+            h
+        })
+        .danger_accept_invalid_certs(true)
+        .timeout(Duration::from_secs(30))
+        .connect_timeout(Duration::from_secs(10))
+        .build()
+        .unwrap_or_default()
+}
+
+/// 下载媒体用的客户端，重定向由 `download_media` 手动跟随
+pub fn download_client(spec: &MediaSpec) -> reqwest::Client {
+    reqwest::Client::builder()
+        .default_headers({
+            let mut h = HeaderMap::new();
+            h.insert(USER_AGENT, HeaderValue::from_static(BROWSER_UA));
+            if let Some(referer) = spec.referer {
+                h.insert(REFERER, HeaderValue::from_static(referer));
+            }
+            h
+        })
+        .redirect(reqwest::redirect::Policy::none())
+        .danger_accept_invalid_certs(true)
+        .timeout(Duration::from_secs(spec.download_timeout_secs))
+        .connect_timeout(Duration::from_secs(spec.connect_timeout_secs))
+        .build()
+        .unwrap_or_default()
+}
+
+/// 按接口声明的响应类型解析，类型不符时给出明确错误
+async fn resolve_declared(
+    endpoint: &ApiEndpoint,
+    resp: reqwest::Response,
+    spec: &MediaSpec,
+) -> Result<String, String> {
+    let status = resp.status();
+    let final_url = resp.url().clone();
+    let content_type = resp
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let redirect_location = || {
+        resp.headers()
+            .get(LOCATION)
+            .map(header_value_to_string)
+            .and_then(|loc| resolve_redirect_location(&final_url, &loc))
+            .ok_or_else(|| "重定向但无Location头".to_string())
+    };
+
+    match endpoint.kind {
+        ResponseKind::Redirect => {
+            if status.is_redirection() {
+                return redirect_location();
+            }
+            // 跟随重定向的客户端看到的是最终地址
+            let redirected = reqwest::Url::parse(&endpoint.url)
+                .map(|u| u != final_url)
+                .unwrap_or(true);
+            if redirected {
+                return Ok(final_url.to_string());
+            }
+            Err(format!(
+                "接口声明为重定向，但未发生重定向（状态码 {}）",
+                status
+            ))
+        }
+        ResponseKind::Stream => {
+            if status.is_redirection() {
+                return redirect_location();
+            }
+            if !status.is_success() {
+                return Err(format!("接口声明为直链，但返回状态码 {}", status));
+            }
+            if content_type.starts_with("text/") || content_type.contains("json") {
+                return Err(format!("接口声明为直链，但响应类型为 {}", content_type));
+            }
+            Ok(final_url.to_string())
+        }
+        ResponseKind::Json | ResponseKind::Text | ResponseKind::Html => {
+            if !status.is_success() {
+                return Err(format!("HTTP错误: {}", status));
+            }
+            let text = resp
+                .text()
+                .await
+                .map_err(|e| format!("读取响应失败: {}", e))?;
+
+            if let Some(url) = extract_with_rule(endpoint, &final_url, &text)? {
+                return Ok(url);
+            }
+
+            let trimmed = text.trim();
+            match endpoint.kind {
+                ResponseKind::Json => {
+                    let value: Value = serde_json::from_str(trimmed)
+                        .map_err(|e| format!("接口声明为JSON，但响应无法解析: {}", e))?;
+                    (spec.json_fallback)(&value)
+                }
+                ResponseKind::Text => {
+                    if trimmed.starts_with("http://") || trimmed.starts_with("https://") {
+                        Ok(trimmed.to_string())
+                    } else {
+                        Err("接口声明为纯文本，但响应不是URL".into())
+                    }
+                }
+                _ => find_media_in_html(trimmed, spec)
+                    .and_then(|url| resolve_redirect_location(&final_url, &url))
+                    .ok_or_else(|| "接口声明为HTML，但页面中未找到媒体地址".to_string()),
+            }
+        }
+        ResponseKind::Auto => Err("自动识别的接口不走声明式解析".into()),
+    }
+}
+
+/// 请求接口并解析出媒体地址
+pub async fn resolve_endpoint(endpoint: &ApiEndpoint, spec: &MediaSpec) -> Result<String, String> {
+    let api_url = endpoint.url.clone();
+    let resp = resolver_client()
+        .get(&api_url)
+        .send()
+        .await
+        .map_err(|e| format!("请求失败: {}", e))?;
+
+    let final_url = resp.url().to_string();
+    let status = resp.status();
+
+    if !endpoint.kind.is_auto() {
+        return resolve_declared(endpoint, resp, spec).await;
+    }
+
+    if status.is_redirection() {
+        if let Some(location) = resp
+            .headers()
+            .get(LOCATION)
+            .map(header_value_to_string)
+            .and_then(|loc| resolve_redirect_location(resp.url(), &loc))
+        {
+            return Ok(location);
+        }
+        return Err("重定向但无Location头".into());
+    }
+
+    let content_type = resp
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+
+    // 直接返回媒体流的接口（URL 可能不含扩展名）
+    if content_type.starts_with(spec.content_type_prefix)
+        || content_type.starts_with("application/octet-stream")
+    {
+        return Ok(final_url);
+    }
+
+    // 如果最终URL是媒体文件，直接返回
+    if spec
+        .url_extensions
+        .iter()
+        .any(|ext| final_url.contains(ext))
+    {
+        return Ok(final_url);
+    }
+
+    // 尝试解析响应内容
+    let base_url = resp.url().clone();
+    let text = resp
+        .text()
+        .await
+        .map_err(|e| format!("读取响应失败: {}", e))?;
+
+    if let Some(url) = extract_with_rule(endpoint, &base_url, &text)? {
+        return Ok(url);
+    }
+
+    let trimmed = text.trim();
+    if trimmed.starts_with('{') {
+        let value: Value = serde_json::from_str(trimmed).map_err(|e| format!("解析失败: {}", e))?;
+        return (spec.json_fallback)(&value);
+    }
+
+    if trimmed.starts_with("http://") || trimmed.starts_with("https://") {
+        return Ok(trimmed.to_string());
+    }
+
+    // 如果最终URL不同于原始URL，可能是重定向到媒体
+    if final_url != api_url {
+        return Ok(final_url);
+    }
+
+    Err(format!("未知响应格式, 状态码: {}", status))
+}
+
+pub(crate) fn too_large(spec: &MediaSpec) -> String {
+    format!("{}文件过大 (>{}MB)", spec.label, spec.max_bytes / MB)
+}
+
+/// 跟随重定向并检查声明的大小，返回尚未读取正文的响应
+pub async fn open_media(kind: MediaKind, url: &str) -> Result<reqwest::Response, String> {
+    let spec = kind.spec();
+    let client = download_client(spec);
+
+    // 先尝试直接下载，如果是重定向则跟随
+    let mut current_url = url.to_string();
+    let mut redirect_count = 0;
+
+    let resp = loop {
+        let resp = client
+            .get(&current_url)
+            .send()
+            .await
+            .map_err(|e| format!("下载失败: {}", e))?;
+
+        if resp.status().is_redirection() {
+            if redirect_count >= 5 {
+                return Err("重定向次数过多".into());
+            }
+
+            let location = resp
+                .headers()
+                .get(LOCATION)
+                .map(header_value_to_string)
+                .and_then(|loc| resolve_redirect_location(resp.url(), &loc));
+
+            if let Some(next_url) = location {
+                current_url = next_url;
+                redirect_count += 1;
+                continue;
+            }
+
+            return Err("重定向但无Location头".into());
+        }
+
+        if !resp.status().is_success() {
+            return Err(format!("HTTP错误: {}", resp.status()));
+        }
+
+        break resp;
+    };
+
+    if resp.content_length().unwrap_or(0) > spec.max_bytes {
+        return Err(too_large(spec));
+    }
+    Ok(resp)
+}
+
+/// 下载完整内容；HLS 播放列表会下载全部分片并拼接
+pub async fn download_media(kind: MediaKind, url: &str) -> Result<Bytes, String> {
+    let spec = kind.spec();
+    let resp = open_media(kind, url).await?;
+    let content_type = resp
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    if kind == MediaKind::Video && is_playlist(resp.url().as_str(), content_type) {
+        return hls::download(resp, spec).await;
+    }
+
+    let data = resp
+        .bytes()
+        .await
+        .map_err(|e| format!("读取{}失败: {}", spec.label, e))?;
+
+    if data.len() as u64 > spec.max_bytes {
+        return Err(too_large(spec));
+    }
+
+    Ok(data)
+}
+
+/// 代理播放用的客户端，不跟随重定向
+pub fn build_client() -> reqwest::Client {
+    let mut headers = HeaderMap::new();
+    headers.insert(USER_AGENT, HeaderValue::from_static(BROWSER_UA));
+    headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+    headers.insert(ACCEPT_LANGUAGE, HeaderValue::from_static("zh-CN,zh;q=0.9"));
+
+    reqwest::Client::builder()
+        .default_headers(headers)
+        .redirect(reqwest::redirect::Policy::none())
+        .danger_accept_invalid_certs(true)
+        .timeout(Duration::from_secs(30))
+        .connect_timeout(Duration::from_secs(10))
+        .build()
+        .unwrap_or_default()
+}
+
+pub fn resolve_redirect_location(base: &reqwest::Url, location: &str) -> Option<String> {
+    let location = location.trim();
+    if location.is_empty() {
+        return None;
+    }
+    base.join(location)
+        .map(|url| url.to_string())
+        .ok()
+        .or_else(|| Some(location.to_string()))
+}
+
+pub fn header_value_to_string(value: &HeaderValue) -> String {
+    if let Ok(s) = value.to_str() {
+        s.to_string()
+    } else {
+        String::from_utf8_lossy(value.as_bytes()).to_string()
+    }
+}
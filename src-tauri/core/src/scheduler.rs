@@ -0,0 +1,145 @@
+//! 后台预加载调度：保持视频预加载队列满，连续失败时退避，暂停期间不开始新的下载
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Notify};
+use tokio::time::Instant;
+
+use crate::engine::Engine;
+use crate::preload::PreloadOutcome;
+
+/// 第一次失败后的重试间隔，之后每次翻倍
+pub const RETRY_BASE: Duration = Duration::from_secs(2);
+
+/// 重试间隔的上限
+pub const RETRY_MAX: Duration = Duration::from_secs(120);
+
+/// 没有被唤醒时也定期检查一次，缓存淘汰后可能又有了空间
+const IDLE_RECHECK: Duration = Duration::from_secs(30);
+
+/// 调度过程中的事件，桌面端按类型转发为不同的前端事件，载荷只含字段
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum PreloadEvent {
+    #[serde(rename_all = "camelCase")]
+    Added {
+        id: String,
+        url: String,
+        queue_len: usize,
+    },
+    #[serde(rename_all = "camelCase")]
+    Failed {
+        error: String,
+        /// 连续失败的次数
+        failures: u32,
+        retry_in_ms: u64,
+    },
+}
+
+/// 第 `failures` 次连续失败后的等待时间
+pub fn retry_delay(failures: u32) -> Duration {
+    let exp = failures.saturating_sub(1).min(16);
+    RETRY_BASE.saturating_mul(1 << exp).min(RETRY_MAX)
+}
+
+/// 调度器的状态；出队、清空与修改设置时唤醒，使队列尽快补满
+#[derive(Default)]
+pub struct PreloadScheduler {
+    wake: Notify,
+    paused: AtomicBool,
+}
+
+impl PreloadScheduler {
+    pub fn wake(&self) {
+        self.wake.notify_one();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// 暂停只影响之后的补充，进行中的下载会继续完成
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::SeqCst);
+        if !paused {
+            self.wake();
+        }
+    }
+
+    /// 一直运行的调度循环：每个空闲名额启动一个独立的下载任务，任一任务结束或被唤醒时立即补充。
+    /// `on_event` 在条目入队（此时下载可能仍在进行）或下载失败时调用，被取消的下载不会触发
+    pub async fn run(
+        &self,
+        engine: Arc<Engine>,
+        on_event: impl Fn(PreloadEvent) + Send + Sync + 'static,
+    ) {
+        let on_event = Arc::new(on_event);
+        let (done_tx, mut done_rx) = mpsc::unbounded_channel();
+        let mut failures = 0u32;
+        let mut retry_at: Option<Instant> = None;
+        loop {
+            // 退避期间不开始新的下载，也不响应唤醒，避免出队时立即重试失败的接口
+            let backoff = retry_at.filter(|at| *at > Instant::now());
+            if !self.is_paused() && backoff.is_none() {
+                while let Some(mut slot) = engine.preload.try_begin() {
+                    let on_added = on_event.clone();
+                    let engine = engine.clone();
+                    let done_tx = done_tx.clone();
+                    slot.on_queued(move |video, queue_len| {
+                        on_added(PreloadEvent::Added {
+                            id: video.id,
+                            url: video.url,
+                            queue_len,
+                        })
+                    });
+                    tokio::spawn(async move {
+                        let result = engine.preload_one(&mut slot).await;
+                        // 先归还名额，调度器收到结果时即可补充
+                        drop(slot);
+                        let _ = done_tx.send(result);
+                    });
+                }
+            }
+
+            // 暂停、队列已满、名额用完或超出缓存容量时等待；缓存淘汰后可能又有了空间，定期检查一次
+            let deadline = backoff.unwrap_or_else(|| Instant::now() + IDLE_RECHECK);
+            tokio::select! {
+                _ = self.wake.notified(), if backoff.is_none() => {}
+                Some(result) = done_rx.recv() => match result {
+                    Ok(PreloadOutcome::Queued(_)) => failures = 0,
+                    // 取消的下载不计入失败，也不退避
+                    Ok(PreloadOutcome::Cancelled) => {}
+                    Err(error) => {
+                        eprintln!("preload: {}", error);
+                        failures = failures.saturating_add(1);
+                        let delay = retry_delay(failures);
+                        retry_at = Some(Instant::now() + delay);
+                        on_event(PreloadEvent::Failed {
+                            error,
+                            failures,
+                            retry_in_ms: delay.as_millis() as u64,
+                        });
+                    }
+                },
+                _ = tokio::time::sleep_until(deadline) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_doubles_up_to_max() {
+        assert_eq!(retry_delay(0), RETRY_BASE);
+        assert_eq!(retry_delay(1), RETRY_BASE);
+        assert_eq!(retry_delay(2), RETRY_BASE * 2);
+        assert_eq!(retry_delay(4), RETRY_BASE * 8);
+        assert_eq!(retry_delay(7), RETRY_MAX);
+        assert_eq!(retry_delay(u32::MAX), RETRY_MAX);
+    }
+}
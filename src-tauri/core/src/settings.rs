@@ -0,0 +1,266 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::catalog::{Catalog, CustomEndpoint};
+use crate::media::MediaKind;
+use crate::model::{ApiCategory, RANDOM_CATEGORY};
+use crate::preload::PreloadSettings;
+
+pub const SETTINGS_VERSION: u32 = 2;
+pub const SETTINGS_FILE: &str = "settings.json";
+
+#[derive(Serialize, Deserialize)]
+pub struct StoredSettings {
+    pub version: u32,
+    pub catalogs: BTreeMap<MediaKind, Catalog>,
+    /// 旧文件中没有该字段时使用默认值
+    #[serde(default)]
+    pub preload: PreloadSettings,
+    /// 收藏库目录，未设置时使用设置文件旁的 `favorites/`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub library_dir: Option<PathBuf>,
+}
+
+impl Default for StoredSettings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            catalogs: MediaKind::ALL
+                .iter()
+                .map(|kind| (*kind, Catalog::default()))
+                .collect(),
+            preload: PreloadSettings::default(),
+            library_dir: None,
+        }
+    }
+}
+
+/// v1 按视频/图片分别平铺字段
+#[derive(Deserialize)]
+#[serde(default)]
+struct SettingsV1 {
+    current_category: String,
+    custom_categories: Vec<ApiCategory>,
+    custom_endpoints: Vec<CustomEndpoint>,
+    current_image_category: String,
+    custom_image_categories: Vec<ApiCategory>,
+    custom_image_endpoints: Vec<CustomEndpoint>,
+}
+
+impl Default for SettingsV1 {
+    fn default() -> Self {
+        Self {
+            current_category: RANDOM_CATEGORY.into(),
+            custom_categories: Vec::new(),
+            custom_endpoints: Vec::new(),
+            current_image_category: RANDOM_CATEGORY.into(),
+            custom_image_categories: Vec::new(),
+            custom_image_endpoints: Vec::new(),
+        }
+    }
+}
+
+impl From<SettingsV1> for StoredSettings {
+    fn from(v1: SettingsV1) -> Self {
+        let mut settings = StoredSettings::default();
+        settings.catalogs.insert(
+            MediaKind::Video,
+            Catalog {
+                current_category: v1.current_category,
+                custom_categories: v1.custom_categories,
+                custom_endpoints: v1.custom_endpoints,
+            },
+        );
+        settings.catalogs.insert(
+            MediaKind::Image,
+            Catalog {
+                current_category: v1.current_image_category,
+                custom_categories: v1.custom_image_categories,
+                custom_endpoints: v1.custom_image_endpoints,
+            },
+        );
+        settings
+    }
+}
+
+/// 与 Tauri 的 `app_data_dir()` 保持一致：{data_dir}/{identifier}
+pub fn app_data_dir(identifier: &str) -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(identifier)
+}
+
+/// 与 Tauri 的 `app_cache_dir()` 保持一致：{cache_dir}/{identifier}
+pub fn app_cache_dir(identifier: &str) -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(identifier)
+}
+
+fn parse_settings(text: &str) -> Result<StoredSettings, String> {
+    let value: Value = serde_json::from_str(text).map_err(|e| format!("解析失败: {}", e))?;
+    let version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(0);
+
+    let mut settings = match version {
+        // v0（无版本号）与 v1 结构一致
+        0 | 1 => serde_json::from_value::<SettingsV1>(value)
+            .map(StoredSettings::from)
+            .map_err(|e| format!("解析失败: {}", e))?,
+        2 => serde_json::from_value::<StoredSettings>(value)
+            .map_err(|e| format!("解析失败: {}", e))?,
+        _ => return Err(format!("设置文件版本过新: {}", version)),
+    };
+
+    settings.version = SETTINGS_VERSION;
+    settings.preload.normalize();
+    for kind in MediaKind::ALL {
+        settings
+            .catalogs
+            .entry(kind)
+            .or_default()
+            .normalize(kind.spec());
+    }
+    Ok(settings)
+}
+
+/// 只读取设置，出错时原样返回错误而不备份文件，供命令行等只读场景使用
+pub fn read_settings(path: &Path) -> Result<StoredSettings, String> {
+    match fs::read_to_string(path) {
+        Ok(text) => parse_settings(&text),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(StoredSettings::default()),
+        Err(e) => Err(format!("读取失败 {}: {}", path.display(), e)),
+    }
+}
+
+pub fn load_settings(path: &Path) -> StoredSettings {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return StoredSettings::default(),
+        Err(e) => {
+            eprintln!("load_settings: 读取失败 {}: {}", path.display(), e);
+            return StoredSettings::default();
+        }
+    };
+
+    match parse_settings(&text) {
+        Ok(settings) => settings,
+        Err(e) => {
+            back_up(path, &e);
+            StoredSettings::default()
+        }
+    }
+}
+
+/// 保留无法识别的文件，避免下次保存时被覆盖
+fn back_up(path: &Path, error: &str) {
+    let backup = path.with_extension("json.bak");
+    eprintln!(
+        "{}: {}，已备份到 {}",
+        path.display(),
+        error,
+        backup.display()
+    );
+    let _ = fs::rename(path, &backup);
+}
+
+/// 读取带 `version` 字段、由 [`write_json_atomic`] 写入的文件；文件不存在时返回 `None`，
+/// 无法解析或版本不是 `version` 时改名备份后返回 `None`
+pub fn load_versioned_json<T: DeserializeOwned>(path: &Path, version: u32) -> Option<T> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                eprintln!("{}: 读取失败: {}", path.display(), e);
+            }
+            return None;
+        }
+    };
+
+    let parsed = serde_json::from_str::<Value>(&text)
+        .map_err(|e| format!("解析失败: {}", e))
+        .and_then(|value| {
+            match value.get("version").and_then(|v| v.as_u64()) {
+                Some(v) if v == u64::from(version) => {}
+                v => return Err(format!("不支持的版本: {}", v.unwrap_or(0))),
+            }
+            serde_json::from_value(value).map_err(|e| format!("解析失败: {}", e))
+        });
+    match parsed {
+        Ok(value) => Some(value),
+        Err(e) => {
+            back_up(path, &e);
+            None
+        }
+    }
+}
+
+/// 默认的设置文件位置
+pub fn settings_path(identifier: &str) -> PathBuf {
+    app_data_dir(identifier).join(SETTINGS_FILE)
+}
+
+pub fn write_settings_atomic(path: &Path, settings: &StoredSettings) -> Result<(), String> {
+    write_json_atomic(path, settings)
+}
+
+/// 先写临时文件再改名，写入中途退出不会损坏原文件
+pub fn write_json_atomic<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("创建目录失败: {}", e))?;
+    }
+
+    let json = serde_json::to_vec_pretty(value).map_err(|e| format!("序列化失败: {}", e))?;
+    let tmp_path = path.with_extension("json.tmp");
+    {
+        let mut file = fs::File::create(&tmp_path).map_err(|e| format!("写入失败: {}", e))?;
+        file.write_all(&json)
+            .and_then(|_| file.sync_all())
+            .map_err(|e| format!("写入失败: {}", e))?;
+    }
+    fs::rename(&tmp_path, path).map_err(|e| format!("写入失败: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct Stored {
+        version: u32,
+        entries: Vec<u32>,
+    }
+
+    fn temp_file(text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("versioned-{}.json", uuid::Uuid::new_v4()));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn loads_matching_version() {
+        let path = temp_file(r#"{"version":1,"entries":[1,2]}"#);
+        let stored = load_versioned_json::<Stored>(&path, 1).unwrap();
+        assert_eq!(stored.entries, [1, 2]);
+        assert!(path.exists());
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn backs_up_other_versions_and_garbage() {
+        for text in [r#"{"version":2,"entries":[]}"#, r#"{"entries":[]}"#, "{"] {
+            let path = temp_file(text);
+            assert!(load_versioned_json::<Stored>(&path, 1).is_none());
+            let backup = path.with_extension("json.bak");
+            assert!(!path.exists());
+            assert_eq!(fs::read_to_string(&backup).unwrap(), text);
+            let _ = fs::remove_file(backup);
+        }
+        let missing = std::env::temp_dir().join("versioned-missing.json");
+        assert!(load_versioned_json::<Stored>(&missing, 1).is_none());
+    }
+}
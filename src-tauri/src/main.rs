@@ -1,1367 +1,661 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use bytes::Bytes;
-use rand::seq::SliceRandom;
-use reqwest::header::{
-    HeaderMap, HeaderValue, ACCEPT, ACCEPT_LANGUAGE, CONTENT_RANGE, CONTENT_TYPE,
-    LOCATION, RANGE, REFERER, USER_AGENT,
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, Manager, State};
+use video_player_core::favorites::Favorite;
+use video_player_core::history::{HistoryEntry, HistoryItem};
+use video_player_core::preload::{PreloadSettings, QueueEntry, StreamReply, STREAM_WAIT_TIMEOUT};
+use video_player_core::proxy::fetch_window;
+use video_player_core::remote::RemoteVideo;
+use video_player_core::resolver::download_media;
+use video_player_core::scheduler::PreloadEvent;
+use video_player_core::settings::{app_cache_dir, settings_path};
+use video_player_core::{
+    ApiCategory, ApiEndpoint, Engine, ExtractRule, ImportMode, ImportSummary, MediaKind,
+    ResponseKind,
 };
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use std::collections::VecDeque;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Mutex;
-use std::time::{SystemTime, UNIX_EPOCH};
-use tauri::{Manager, State};
-use uuid::Uuid;
 
 // ============================================================
-// 数据结构
+// Tauri Commands
 // ============================================================
 
-struct PreloadedVideo {
-    id: String,
+/// 解析出的媒体：直链与经本地 stream 协议的地址
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MediaSource {
     url: String,
-    data: Bytes,
-}
-
-struct PreloadGuard<'a> {
-    flag: &'a AtomicBool,
+    stream_url: String,
 }
 
-impl<'a> PreloadGuard<'a> {
-    fn try_new(flag: &'a AtomicBool) -> Option<Self> {
-        if flag
-            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
-            .is_ok()
-        {
-            Some(Self { flag })
-        } else {
-            None
-        }
-    }
+fn remote_stream_url(video: &RemoteVideo) -> String {
+    format!("stream:///remote/{}", video.id)
 }
 
-impl Drop for PreloadGuard<'_> {
-    fn drop(&mut self) {
-        self.flag.store(false, Ordering::SeqCst);
-    }
+#[tauri::command]
+async fn fetch_video(state: State<'_, Arc<Engine>>) -> Result<MediaSource, String> {
+    let video = state.fetch_video().await?;
+    Ok(MediaSource {
+        url: video.url.clone(),
+        stream_url: remote_stream_url(&video),
+    })
 }
 
-#[derive(Clone, Serialize, Deserialize)]
-struct ApiEndpoint {
-    id: String,
-    name: String,
-    url: String,
-    builtin: bool,
+#[tauri::command]
+async fn fetch_image(state: State<'_, Arc<Engine>>) -> Result<MediaSource, String> {
+    let image = state.fetch_image().await?;
+    Ok(MediaSource {
+        url: image.url.clone(),
+        stream_url: format!("stream:///image/{}", image.id),
+    })
 }
 
-#[derive(Clone, Serialize, Deserialize)]
-struct ApiCategory {
-    id: String,
-    name: String,
-    builtin: bool,
-    endpoints: Vec<ApiEndpoint>,
+#[tauri::command]
+async fn download_video(url: String) -> Result<Vec<u8>, String> {
+    download_media(MediaKind::Video, &url)
+        .await
+        .map(|data| data.to_vec())
 }
 
-const RANDOM_CATEGORY: &str = "random";
-
-fn now_millis() -> u128 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_millis()
+#[tauri::command]
+async fn download_image(state: State<'_, Arc<Engine>>, url: String) -> Result<Vec<u8>, String> {
+    state.download_image(&url).await
 }
 
-fn get_builtin_categories() -> Vec<ApiCategory> {
-    vec![
-        ApiCategory {
-            id: "taozi".into(),
-            name: "桃子API".into(),
-            builtin: true,
-            endpoints: vec![
-                ApiEndpoint {
-                    id: "taozi_1".into(),
-                    name: "抖音快手美女".into(),
-                    url: "https://api.tzjsy.cn/sp/dyksmn/video.php".into(),
-                    builtin: true,
-                },
-                ApiEndpoint {
-                    id: "taozi_2".into(),
-                    name: "黑丝".into(),
-                    url: "https://api.tzjsy.cn/sp/hs/video.php".into(),
-                    builtin: true,
-                },
-                ApiEndpoint {
-                    id: "taozi_3".into(),
-                    name: "白丝".into(),
-                    url: "https://api.tzjsy.cn/sp/bs/video.php".into(),
-                    builtin: true,
-                },
-                ApiEndpoint {
-                    id: "taozi_4".into(),
-                    name: "JK制服".into(),
-                    url: "https://api.tzjsy.cn/sp/jk/video.php".into(),
-                    builtin: true,
-                },
-                ApiEndpoint {
-                    id: "taozi_5".into(),
-                    name: "甜美".into(),
-                    url: "https://api.tzjsy.cn/sp/tm/video.php".into(),
-                    builtin: true,
-                },
-                ApiEndpoint {
-                    id: "taozi_6".into(),
-                    name: "纯欲".into(),
-                    url: "https://api.tzjsy.cn/sp/cy/video.php".into(),
-                    builtin: true,
-                },
-                ApiEndpoint {
-                    id: "taozi_7".into(),
-                    name: "QC".into(),
-                    url: "https://api.tzjsy.cn/sp/qc/video.php".into(),
-                    builtin: true,
-                },
-                ApiEndpoint {
-                    id: "taozi_8".into(),
-                    name: "LL".into(),
-                    url: "https://api.tzjsy.cn/sp/ll/video.php".into(),
-                    builtin: true,
-                },
-                ApiEndpoint {
-                    id: "taozi_9".into(),
-                    name: "YZ".into(),
-                    url: "https://api.tzjsy.cn/sp/yz/video.php".into(),
-                    builtin: true,
-                },
-                ApiEndpoint {
-                    id: "taozi_10".into(),
-                    name: "515".into(),
-                    url: "https://api.tzjsy.cn/sp/515/video.php".into(),
-                    builtin: true,
-                },
-                ApiEndpoint {
-                    id: "taozi_11".into(),
-                    name: "COS".into(),
-                    url: "https://api.tzjsy.cn/sp/cos/video.php".into(),
-                    builtin: true,
-                },
-                ApiEndpoint {
-                    id: "taozi_12".into(),
-                    name: "YOZ".into(),
-                    url: "https://api.tzjsy.cn/sp/yoz/video.php".into(),
-                    builtin: true,
-                },
-            ],
-        },
-        ApiCategory {
-            id: "wanfeng".into(),
-            name: "晚风API".into(),
-            builtin: true,
-            endpoints: vec![ApiEndpoint {
-                id: "wanfeng_1".into(),
-                name: "跳舞视频".into(),
-                url: "http://api.nonebot.top/api/v1/random/dance_video".into(),
-                builtin: true,
-            }],
-        },
-        ApiCategory {
-            id: "huai".into(),
-            name: "Huai API".into(),
-            builtin: true,
-            endpoints: vec![ApiEndpoint {
-                id: "huai_1".into(),
-                name: "抖音视频".into(),
-                url: "http://api.huaiyan.top:81/api/dy?type=mp4".into(),
-                builtin: true,
-            }],
-        },
-    ]
+#[tauri::command]
+async fn preload_next(state: State<'_, Arc<Engine>>) -> Result<usize, String> {
+    Ok(state.preload_next().await)
 }
 
-fn get_builtin_image_categories() -> Vec<ApiCategory> {
-    vec![
-        ApiCategory {
-            id: "btstu".into(),
-            name: "BTSTU美图".into(),
-            builtin: true,
-            endpoints: vec![
-                ApiEndpoint {
-                    id: "btstu_1".into(),
-                    name: "随机美女(直连)".into(),
-                    url: "https://api.btstu.cn/sjbz/api.php?lx=meizi".into(),
-                    builtin: true,
-                },
-                ApiEndpoint {
-                    id: "btstu_2".into(),
-                    name: "随机美女(JSON)".into(),
-                    url: "https://api.btstu.cn/sjbz/api.php?lx=meizi&format=json".into(),
-                    builtin: true,
-                },
-            ],
-        },
-        ApiCategory {
-            id: "wanfeng".into(),
-            name: "晚风API".into(),
-            builtin: true,
-            endpoints: vec![ApiEndpoint {
-                id: "wanfeng_1".into(),
-                name: "晚风api".into(),
-                url: "http://api.nonebot.top/api/v1/random/tuwan".into(),
-                builtin: true,
-            }],
-        },
-    ]
+#[tauri::command]
+fn get_preload_count(state: State<'_, Arc<Engine>>) -> usize {
+    state.preload.len()
 }
 
-// ============================================================
-// 应用状态
-// ============================================================
-
-struct AppState {
-    current_video_url: Mutex<Option<String>>,
-    current_category: Mutex<String>,
-    custom_categories: Mutex<Vec<ApiCategory>>,
-    custom_endpoints: Mutex<Vec<(String, ApiEndpoint)>>,
-    current_image_category: Mutex<String>,
-    custom_image_categories: Mutex<Vec<ApiCategory>>,
-    custom_image_endpoints: Mutex<Vec<(String, ApiEndpoint)>>,
-    preload_queue: Mutex<VecDeque<PreloadedVideo>>,
-    playing_video: Mutex<Option<PreloadedVideo>>,
-    preload_in_progress: AtomicBool,
+#[tauri::command]
+fn pop_next_video(state: State<'_, Arc<Engine>>) -> Result<MediaSource, String> {
+    let (id, remote) = state
+        .pop_next_video()
+        .ok_or_else(|| "没有预加载的视频".to_string())?;
+    Ok(MediaSource {
+        url: format!("stream:///video/{}", id),
+        stream_url: remote_stream_url(&remote),
+    })
 }
 
-fn get_all_categories(state: &State<AppState>) -> Vec<ApiCategory> {
-    let mut categories = get_builtin_categories();
-    let custom_categories = state.custom_categories.lock().unwrap();
-    let custom_endpoints = state.custom_endpoints.lock().unwrap();
-
-    // 添加自定义接口到内置分类
-    for cat in categories.iter_mut() {
-        for (cat_id, ep) in custom_endpoints.iter() {
-            if cat_id == &cat.id {
-                cat.endpoints.push(ep.clone());
-            }
-        }
-    }
-
-    // 添加自定义分类
-    for custom_cat in custom_categories.iter() {
-        let mut cat = custom_cat.clone();
-        for (cat_id, ep) in custom_endpoints.iter() {
-            if cat_id == &cat.id {
-                cat.endpoints.push(ep.clone());
-            }
-        }
-        categories.push(cat);
-    }
-
-    categories
+#[tauri::command]
+fn clear_preload_queue(state: State<'_, Arc<Engine>>) {
+    state.preload.clear();
+    state.scheduler.wake();
 }
 
-fn get_all_image_categories(state: &State<AppState>) -> Vec<ApiCategory> {
-    let mut categories = get_builtin_image_categories();
-    let custom_categories = state.custom_image_categories.lock().unwrap();
-    let custom_endpoints = state.custom_image_endpoints.lock().unwrap();
-
-    // 添加自定义接口到内置分类
-    for cat in categories.iter_mut() {
-        for (cat_id, ep) in custom_endpoints.iter() {
-            if cat_id == &cat.id {
-                cat.endpoints.push(ep.clone());
-            }
-        }
-    }
-
-    // 添加自定义分类
-    for custom_cat in custom_categories.iter() {
-        let mut cat = custom_cat.clone();
-        for (cat_id, ep) in custom_endpoints.iter() {
-            if cat_id == &cat.id {
-                cat.endpoints.push(ep.clone());
-            }
-        }
-        categories.push(cat);
-    }
-
-    categories
+/// 预加载队列中的视频，按播放顺序排列
+#[tauri::command]
+fn list_preload_queue(state: State<'_, Arc<Engine>>) -> Vec<QueueEntry> {
+    state.preload.entries()
 }
 
-fn category_exists(state: &State<AppState>, category_id: &str) -> bool {
-    if category_id == RANDOM_CATEGORY {
-        return true;
+#[tauri::command]
+fn remove_preloaded_video(state: State<'_, Arc<Engine>>, id: String) -> Result<(), String> {
+    if !state.preload.remove(&id) {
+        return Err("预加载队列中没有该视频".into());
     }
-    get_all_categories(state).iter().any(|c| c.id == category_id)
+    state.scheduler.wake();
+    Ok(())
 }
 
-fn image_category_exists(state: &State<AppState>, category_id: &str) -> bool {
-    if category_id == RANDOM_CATEGORY {
-        return true;
+#[tauri::command]
+fn move_preloaded_to_front(state: State<'_, Arc<Engine>>, id: String) -> Result<(), String> {
+    if !state.preload.move_to_front(&id) {
+        return Err("预加载队列中没有该视频".into());
     }
-    get_all_image_categories(state)
-        .iter()
-        .any(|c| c.id == category_id)
-}
-
-// ============================================================
-// API 响应解析
-// ============================================================
-
-#[derive(Deserialize)]
-struct ApiResponse {
-    code: i32,
-    #[allow(dead_code)]
-    msg: Option<String>,
-    data: String,
+    Ok(())
 }
 
-fn extract_image_url_from_json(value: &Value) -> Option<String> {
-    if let Value::String(url) = value {
-        return Some(url.clone());
-    }
-
-    let direct_keys = ["data", "imgurl", "url", "image", "pic"];
-    for key in direct_keys {
-        if let Some(url) = value.get(key).and_then(|v| v.as_str()) {
-            return Some(url.to_string());
-        }
-    }
-
-    if let Some(data) = value.get("data") {
-        for key in ["url", "imgurl", "image", "pic"] {
-            if let Some(url) = data.get(key).and_then(|v| v.as_str()) {
-                return Some(url.to_string());
-            }
-        }
+/// 固定的视频在切换分类时保留
+#[tauri::command]
+fn pin_preloaded_video(
+    state: State<'_, Arc<Engine>>,
+    id: String,
+    pinned: bool,
+) -> Result<(), String> {
+    if !state.preload.set_pinned(&id, pinned) {
+        return Err("预加载队列中没有该视频".into());
     }
-
-    None
+    Ok(())
 }
 
-fn build_client() -> reqwest::Client {
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        USER_AGENT,
-        HeaderValue::from_static(
-            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 Chrome/120.0.0.0",
+/// 重新播放一条记录，预加载缓存中还有文件时直接读取缓存
+async fn replay(state: &Engine, entry: HistoryEntry) -> Result<MediaSource, String> {
+    let (cached, remote) = state.play_history(entry).await?;
+    Ok(MediaSource {
+        url: cached.map_or_else(
+            || remote.url.clone(),
+            |id| format!("stream:///video/{}", id),
         ),
-    );
-    headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
-    headers.insert(ACCEPT_LANGUAGE, HeaderValue::from_static("zh-CN,zh;q=0.9"));
-
-    reqwest::Client::builder()
-        .default_headers(headers)
-        .redirect(reqwest::redirect::Policy::none())
-        .danger_accept_invalid_certs(true)
-        .timeout(std::time::Duration::from_secs(30))
-        .connect_timeout(std::time::Duration::from_secs(10))
-        .build()
-        .unwrap_or_default()
+        stream_url: remote_stream_url(&remote),
+    })
 }
 
-fn resolve_redirect_location(base: &reqwest::Url, location: &str) -> Option<String> {
-    let location = location.trim();
-    if location.is_empty() {
-        return None;
+/// 播放记录中的上一个视频，已是最早一条时返回 `None`
+#[tauri::command]
+async fn history_back(state: State<'_, Arc<Engine>>) -> Result<Option<MediaSource>, String> {
+    match state.history.back() {
+        Some(entry) => replay(&state, entry).await.map(Some),
+        None => Ok(None),
     }
-    base.join(location)
-        .map(|url| url.to_string())
-        .ok()
-        .or_else(|| Some(location.to_string()))
 }
 
-fn header_value_to_string(value: &HeaderValue) -> String {
-    if let Ok(s) = value.to_str() {
-        s.to_string()
-    } else {
-        String::from_utf8_lossy(value.as_bytes()).to_string()
+/// 后退之后的下一个视频，已回到最新一条时返回 `None`
+#[tauri::command]
+async fn history_forward(state: State<'_, Arc<Engine>>) -> Result<Option<MediaSource>, String> {
+    match state.history.forward() {
+        Some(entry) => replay(&state, entry).await.map(Some),
+        None => Ok(None),
     }
 }
 
-// ============================================================
-// Tauri Commands
-// ============================================================
-
 #[tauri::command]
-async fn fetch_video(state: State<'_, AppState>) -> Result<String, String> {
-    let api_url = {
-        let current = state.current_category.lock().unwrap().clone();
-        let categories = get_all_categories(&state);
-
-        let candidates: Vec<&ApiEndpoint> = if current == RANDOM_CATEGORY {
-            categories.iter().flat_map(|c| &c.endpoints).collect()
-        } else {
-            categories
-                .iter()
-                .find(|c| c.id == current)
-                .map(|c| c.endpoints.iter().collect())
-                .unwrap_or_default()
-        };
-
-        if candidates.is_empty() {
-            return Err("没有可用的接口".into());
-        }
-
-        let mut rng = rand::thread_rng();
-        candidates.choose(&mut rng).unwrap().url.clone()
-    };
-
-    // 使用跟随重定向的客户端获取最终视频URL
-    let client = reqwest::Client::builder()
-        .default_headers({
-            let mut h = HeaderMap::new();
-            h.insert(USER_AGENT, HeaderValue::from_static(
-                "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 Chrome/120.0.0.0"
-            ));
-            h.insert(ACCEPT, HeaderValue::from_static("*/*"));
-            h
-        })
-        .danger_accept_invalid_certs(true)
-        .timeout(std::time::Duration::from_secs(30))
-        .connect_timeout(std::time::Duration::from_secs(10))
-        .build()
-        .unwrap_or_default();
-
-    let resp = client
-        .get(&api_url)
-        .send()
-        .await
-        .map_err(|e| format!("请求失败: {}", e))?;
-
-    let final_url = resp.url().to_string();
-    let status = resp.status();
-    println!("fetch_video: api={}, final={}, status={}", api_url, final_url, status);
-
-    if status.is_redirection() {
-        if let Some(location) = resp
-            .headers()
-            .get(LOCATION)
-            .map(header_value_to_string)
-            .and_then(|loc| resolve_redirect_location(resp.url(), &loc))
-        {
-            *state.current_video_url.lock().unwrap() = Some(location.clone());
-            return Ok(location);
-        }
-        return Err("重定向但无Location头".into());
-    }
-
-    let content_type = resp
-        .headers()
-        .get(CONTENT_TYPE)
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("")
-        .to_ascii_lowercase();
-
-    // 直接返回视频流的接口（URL 可能不含扩展名）
-    if content_type.starts_with("video/") || content_type.starts_with("application/octet-stream") {
-        *state.current_video_url.lock().unwrap() = Some(final_url.clone());
-        return Ok(final_url);
-    }
-
-    // 如果最终URL是视频文件，直接返回
-    if final_url.contains(".mp4") || final_url.contains(".webm") || final_url.contains(".m3u8") {
-        *state.current_video_url.lock().unwrap() = Some(final_url.clone());
-        return Ok(final_url);
-    }
-
-    // 尝试解析JSON响应
-    let text = resp
-        .text()
-        .await
-        .map_err(|e| format!("读取响应失败: {}", e))?;
-
-    if text.starts_with('{') {
-        let api_resp: ApiResponse =
-            serde_json::from_str(&text).map_err(|e| format!("解析失败: {}", e))?;
-
-        if api_resp.code != 200 {
-            return Err("API返回错误".into());
-        }
-
-        println!("fetch_video: JSON data = {}", api_resp.data);
-        *state.current_video_url.lock().unwrap() = Some(api_resp.data.clone());
-        return Ok(api_resp.data);
-    }
-
-    // 如果最终URL不同于原始URL，可能是重定向到视频
-    if final_url != api_url {
-        *state.current_video_url.lock().unwrap() = Some(final_url.clone());
-        return Ok(final_url);
-    }
-
-    Err(format!("未知响应格式, 状态码: {}", status))
+async fn replay_history(state: State<'_, Arc<Engine>>, id: String) -> Result<MediaSource, String> {
+    let entry = state
+        .history
+        .seek(&id)
+        .ok_or_else(|| "播放记录不存在".to_string())?;
+    replay(&state, entry).await
 }
 
+/// 地址或接口名称包含 `query` 的记录，最新的在前
 #[tauri::command]
-async fn fetch_image(state: State<'_, AppState>) -> Result<String, String> {
-    let api_url = {
-        let current = state.current_image_category.lock().unwrap().clone();
-        let categories = get_all_image_categories(&state);
-
-        let candidates: Vec<&ApiEndpoint> = if current == RANDOM_CATEGORY {
-            categories.iter().flat_map(|c| &c.endpoints).collect()
-        } else {
-            categories
-                .iter()
-                .find(|c| c.id == current)
-                .map(|c| c.endpoints.iter().collect())
-                .unwrap_or_default()
-        };
-
-        if candidates.is_empty() {
-            return Err("没有可用的接口".into());
-        }
-
-        let mut rng = rand::thread_rng();
-        candidates.choose(&mut rng).unwrap().url.clone()
-    };
-
-    let client = reqwest::Client::builder()
-        .default_headers({
-            let mut h = HeaderMap::new();
-            h.insert(
-                USER_AGENT,
-                HeaderValue::from_static(
-                    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 Chrome/120.0.0.0",
-                ),
-            );
-            h.insert(ACCEPT, HeaderValue::from_static("*/*"));
-            h
-        })
-        .danger_accept_invalid_certs(true)
-        .timeout(std::time::Duration::from_secs(30))
-        .connect_timeout(std::time::Duration::from_secs(10))
-        .build()
-        .unwrap_or_default();
-
-    let resp = client
-        .get(&api_url)
-        .send()
-        .await
-        .map_err(|e| format!("请求失败: {}", e))?;
-
-    let final_url = resp.url().to_string();
-    let status = resp.status();
-    println!("fetch_image: api={}, final={}, status={}", api_url, final_url, status);
-
-    if status.is_redirection() {
-        if let Some(location) = resp
-            .headers()
-            .get(LOCATION)
-            .map(header_value_to_string)
-            .and_then(|loc| resolve_redirect_location(resp.url(), &loc))
-        {
-            return Ok(location);
-        }
-        return Err("重定向但无Location头".into());
-    }
-
-    let content_type = resp
-        .headers()
-        .get(CONTENT_TYPE)
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("")
-        .to_ascii_lowercase();
-
-    if content_type.starts_with("image/") || content_type.starts_with("application/octet-stream")
-    {
-        return Ok(final_url);
-    }
-
-    if final_url.contains(".jpg")
-        || final_url.contains(".jpeg")
-        || final_url.contains(".png")
-        || final_url.contains(".webp")
-        || final_url.contains(".gif")
-        || final_url.contains(".bmp")
-    {
-        return Ok(final_url);
-    }
-
-    let text = resp
-        .text()
-        .await
-        .map_err(|e| format!("读取响应失败: {}", e))?;
-
-    let trimmed = text.trim();
-    if trimmed.starts_with('{') {
-        let value: Value =
-            serde_json::from_str(trimmed).map_err(|e| format!("解析失败: {}", e))?;
-        if let Some(url) = extract_image_url_from_json(&value) {
-            return Ok(url);
-        }
-        return Err("JSON中未找到图片地址".into());
-    }
-
-    if trimmed.starts_with("http://") || trimmed.starts_with("https://") {
-        return Ok(trimmed.to_string());
-    }
-
-    if final_url != api_url {
-        return Ok(final_url);
-    }
-
-    Err(format!("未知响应格式, 状态码: {}", status))
+fn search_history(state: State<'_, Arc<Engine>>, query: String, limit: usize) -> Vec<HistoryItem> {
+    state.search_history(&query, limit)
 }
 
-async fn get_video_url_internal(state: &State<'_, AppState>) -> Result<String, String> {
-    let client = build_client();
-
-    let api_url = {
-        let current = state.current_category.lock().unwrap().clone();
-        let categories = get_all_categories(state);
-
-        let candidates: Vec<&ApiEndpoint> = if current == RANDOM_CATEGORY {
-            categories.iter().flat_map(|c| &c.endpoints).collect()
-        } else {
-            categories
-                .iter()
-                .find(|c| c.id == current)
-                .map(|c| c.endpoints.iter().collect())
-                .unwrap_or_default()
-        };
-
-        if candidates.is_empty() {
-            return Err("没有可用的接口".into());
-        }
-
-        let mut rng = rand::thread_rng();
-        candidates.choose(&mut rng).unwrap().url.clone()
-    };
-
-    let resp = client
-        .get(&api_url)
-        .send()
-        .await
-        .map_err(|e| format!("请求失败: {}", e))?;
-
-    let status = resp.status();
-    if status.is_redirection() {
-        if let Some(location) = resp
-            .headers()
-            .get(LOCATION)
-            .map(header_value_to_string)
-            .and_then(|loc| resolve_redirect_location(resp.url(), &loc))
-        {
-            return Ok(location);
-        }
-        return Err("重定向但无Location头".into());
-    }
-
-    let content_type = resp
-        .headers()
-        .get(CONTENT_TYPE)
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("")
-        .to_ascii_lowercase();
-
-    if content_type.starts_with("video/") || content_type.starts_with("application/octet-stream") {
-        return Ok(resp.url().to_string());
-    }
-
-    let text = resp
-        .text()
-        .await
-        .map_err(|e| format!("读取响应失败: {}", e))?;
-
-    if text.starts_with('{') {
-        let api_resp: ApiResponse =
-            serde_json::from_str(&text).map_err(|e| format!("解析失败: {}", e))?;
-
-        if api_resp.code != 200 {
-            return Err("API返回错误".into());
-        }
-
-        return Ok(api_resp.data);
-    }
-
-    Err(format!("未知响应格式, 状态码: {}", status))
+#[tauri::command]
+fn clear_history(state: State<'_, Arc<Engine>>) {
+    state.history.clear();
 }
 
+/// 窗口隐藏或没有在播放视频时暂停后台预加载
 #[tauri::command]
-async fn download_video(url: String) -> Result<Vec<u8>, String> {
-    println!("download_video: {}", url);
-
-    let client = reqwest::Client::builder()
-        .default_headers({
-            let mut h = HeaderMap::new();
-            h.insert(USER_AGENT, HeaderValue::from_static(
-                "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 Chrome/120.0.0.0"
-            ));
-            h.insert(REFERER, HeaderValue::from_static("https://api.tzjsy.cn/"));
-            h
-        })
-        .danger_accept_invalid_certs(true)
-        .timeout(std::time::Duration::from_secs(60))
-        .connect_timeout(std::time::Duration::from_secs(15))
-        .build()
-        .unwrap_or_default();
-
-    // 先尝试直接下载，如果是重定向则跟随
-    let mut current_url = url;
-    let mut redirect_count = 0;
-
-    let resp = loop {
-        let resp = client
-            .get(&current_url)
-            .send()
-            .await
-            .map_err(|e| format!("下载失败: {}", e))?;
-
-        if resp.status().is_redirection() {
-            if redirect_count >= 5 {
-                return Err("重定向次数过多".into());
-            }
-
-            let location = resp
-                .headers()
-                .get(LOCATION)
-                .map(header_value_to_string)
-                .and_then(|loc| resolve_redirect_location(resp.url(), &loc));
-
-            if let Some(next_url) = location {
-                println!("download_video: redirected to {}", next_url);
-                current_url = next_url;
-                redirect_count += 1;
-                continue;
-            }
-
-            return Err("重定向但无Location头".into());
-        }
-
-        if !resp.status().is_success() {
-            return Err(format!("HTTP错误: {}", resp.status()));
-        }
-
-        break resp;
-    };
-
-    let content_length = resp.content_length().unwrap_or(0);
-    println!("download_video: content_length = {}", content_length);
-
-    if content_length > 100 * 1024 * 1024 {
-        return Err("视频文件过大 (>100MB)".into());
-    }
-
-    let data = resp
-        .bytes()
-        .await
-        .map_err(|e| format!("读取视频失败: {}", e))?;
-
-    println!("download_video: downloaded {} bytes", data.len());
-    Ok(data.to_vec())
+fn set_preload_paused(state: State<'_, Arc<Engine>>, paused: bool) {
+    state.scheduler.set_paused(paused);
 }
 
 #[tauri::command]
-async fn download_image(url: String) -> Result<Vec<u8>, String> {
-    println!("download_image: {}", url);
-
-    let client = reqwest::Client::builder()
-        .default_headers({
-            let mut h = HeaderMap::new();
-            h.insert(
-                USER_AGENT,
-                HeaderValue::from_static(
-                    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 Chrome/120.0.0.0",
-                ),
-            );
-            h
-        })
-        .danger_accept_invalid_certs(true)
-        .timeout(std::time::Duration::from_secs(30))
-        .connect_timeout(std::time::Duration::from_secs(10))
-        .build()
-        .unwrap_or_default();
-
-    let mut current_url = url;
-    let mut redirect_count = 0;
-
-    let resp = loop {
-        let resp = client
-            .get(&current_url)
-            .send()
-            .await
-            .map_err(|e| format!("下载失败: {}", e))?;
-
-        if resp.status().is_redirection() {
-            if redirect_count >= 5 {
-                return Err("重定向次数过多".into());
-            }
-
-            let location = resp
-                .headers()
-                .get(LOCATION)
-                .map(header_value_to_string)
-                .and_then(|loc| resolve_redirect_location(resp.url(), &loc));
-
-            if let Some(next_url) = location {
-                println!("download_image: redirected to {}", next_url);
-                current_url = next_url;
-                redirect_count += 1;
-                continue;
-            }
-
-            return Err("重定向但无Location头".into());
-        }
-
-        if !resp.status().is_success() {
-            return Err(format!("HTTP错误: {}", resp.status()));
-        }
-
-        break resp;
-    };
-
-    let content_length = resp.content_length().unwrap_or(0);
-    println!("download_image: content_length = {}", content_length);
-
-    if content_length > 15 * 1024 * 1024 {
-        return Err("图片文件过大 (>15MB)".into());
-    }
-
-    let data = resp
-        .bytes()
-        .await
-        .map_err(|e| format!("读取图片失败: {}", e))?;
-
-    if data.len() > 15 * 1024 * 1024 {
-        return Err("图片文件过大 (>15MB)".into());
-    }
-
-    println!("download_image: downloaded {} bytes", data.len());
-    Ok(data.to_vec())
+async fn preload_next_image(state: State<'_, Arc<Engine>>) -> Result<usize, String> {
+    Ok(state.preload_next_image().await)
 }
 
-const MAX_PRELOAD: usize = 2;
-
-async fn preload_one(state: &State<'_, AppState>) -> Result<(), String> {
-    let url = get_video_url_internal(state).await?;
-
-    let client = reqwest::Client::builder()
-        .default_headers({
-            let mut h = HeaderMap::new();
-            h.insert(USER_AGENT, HeaderValue::from_static(
-                "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 Chrome/120.0.0.0"
-            ));
-            h.insert(REFERER, HeaderValue::from_static("https://api.tzjsy.cn/"));
-            h
-        })
-        .danger_accept_invalid_certs(true)
-        .timeout(std::time::Duration::from_secs(30))
-        .connect_timeout(std::time::Duration::from_secs(10))
-        .build()
-        .unwrap_or_default();
-
-    let resp = client.get(&url).send().await.map_err(|e| format!("下载失败: {}", e))?;
-    if !resp.status().is_success() {
-        return Err(format!("HTTP错误: {}", resp.status()));
-    }
-    if resp.content_length().unwrap_or(0) > 100 * 1024 * 1024 {
-        return Err("视频文件过大".into());
-    }
-
-    let data = resp.bytes().await.map_err(|e| format!("读取失败: {}", e))?;
-    if data.len() > 100 * 1024 * 1024 {
-        return Err("视频文件过大".into());
-    }
-
-    let mut queue = state.preload_queue.lock().unwrap();
-    if queue.len() < MAX_PRELOAD {
-        queue.push_back(PreloadedVideo {
-            id: Uuid::new_v4().to_string(),
-            url,
-            data,
-        });
-    }
-    Ok(())
+#[tauri::command]
+fn get_image_preload_count(state: State<'_, Arc<Engine>>) -> usize {
+    state.image_preload.len()
 }
 
 #[tauri::command]
-async fn preload_next(state: State<'_, AppState>) -> Result<usize, String> {
-    let _guard = match PreloadGuard::try_new(&state.preload_in_progress) {
-        Some(guard) => guard,
-        None => return Ok(state.preload_queue.lock().unwrap().len()),
-    };
-
-    let queue_len = state.preload_queue.lock().unwrap().len();
-    let need = MAX_PRELOAD.saturating_sub(queue_len);
-    if need == 0 {
-        return Ok(queue_len);
-    }
-
-    let _ = preload_one(&state).await;
-
-    Ok(state.preload_queue.lock().unwrap().len())
+fn pop_next_image(state: State<'_, Arc<Engine>>) -> Result<MediaSource, String> {
+    let image = state
+        .pop_next_image()
+        .ok_or_else(|| "没有预加载的图片".to_string())?;
+    Ok(MediaSource {
+        url: image.url.clone(),
+        stream_url: format!("stream:///image/{}", image.id),
+    })
 }
 
+/// 下一张预加载图片的原始地址，不出队
 #[tauri::command]
-fn get_preload_count(state: State<'_, AppState>) -> usize {
-    state.preload_queue.lock().unwrap().len()
+fn peek_next_image(state: State<'_, Arc<Engine>>) -> Option<String> {
+    state.image_preload.peek()
 }
 
 #[tauri::command]
-fn pop_next_video(state: State<'_, AppState>) -> Result<String, String> {
-    let video = state
-        .preload_queue
-        .lock()
-        .unwrap()
-        .pop_front()
-        .ok_or_else(|| "没有预加载的视频".to_string())?;
-
-    let id = video.id.clone();
-    *state.current_video_url.lock().unwrap() = Some(video.url.clone());
-    *state.playing_video.lock().unwrap() = Some(video);
-    Ok(format!("stream:///video/{}", id))
+fn clear_image_preload_queue(state: State<'_, Arc<Engine>>) {
+    state.image_preload.clear();
 }
 
 #[tauri::command]
-fn clear_preload_queue(state: State<'_, AppState>) {
-    state.preload_queue.lock().unwrap().clear();
-    *state.playing_video.lock().unwrap() = None;
+fn get_preload_settings(state: State<'_, Arc<Engine>>) -> PreloadSettings {
+    state.preload_settings()
 }
 
+/// 返回收敛到允许范围后实际生效的设置
 #[tauri::command]
-fn get_categories(state: State<'_, AppState>) -> Vec<ApiCategory> {
-    get_all_categories(&state)
+fn set_preload_settings(
+    state: State<'_, Arc<Engine>>,
+    settings: PreloadSettings,
+) -> Result<PreloadSettings, String> {
+    state.set_preload_settings(settings)
 }
 
 #[tauri::command]
-fn get_current_category(state: State<'_, AppState>) -> String {
-    state.current_category.lock().unwrap().clone()
+fn get_categories(state: State<'_, Arc<Engine>>, media: MediaKind) -> Vec<ApiCategory> {
+    state.catalog(media).categories(media.spec())
 }
 
 #[tauri::command]
-fn set_current_category(state: State<'_, AppState>, category_id: String) -> Result<(), String> {
-    if !category_exists(&state, &category_id) {
-        return Err("分类不存在".into());
-    }
-    *state.current_category.lock().unwrap() = category_id;
-    Ok(())
+fn get_current_category(state: State<'_, Arc<Engine>>, media: MediaKind) -> String {
+    state.catalog(media).current_category.clone()
 }
 
 #[tauri::command]
-fn add_custom_category(state: State<'_, AppState>, name: String) -> Result<ApiCategory, String> {
-    let name = name.trim();
-    if name.is_empty() {
-        return Err("分类名称不能为空".into());
-    }
-
-    let category = ApiCategory {
-        id: format!("custom_cat_{}", now_millis()),
-        name: name.into(),
-        builtin: false,
-        endpoints: vec![],
-    };
+fn set_current_category(
+    state: State<'_, Arc<Engine>>,
+    media: MediaKind,
+    category_id: String,
+) -> Result<(), String> {
+    state.update_catalog(media, |catalog| {
+        catalog.set_current(media.spec(), category_id)
+    })
+}
 
-    state.custom_categories.lock().unwrap().push(category.clone());
-    Ok(category)
+#[tauri::command]
+fn add_custom_category(
+    state: State<'_, Arc<Engine>>,
+    media: MediaKind,
+    name: String,
+) -> Result<ApiCategory, String> {
+    state.update_catalog(media, |catalog| catalog.add_category(media.spec(), &name))
 }
 
 #[tauri::command]
 fn add_custom_api(
-    state: State<'_, AppState>,
+    state: State<'_, Arc<Engine>>,
+    media: MediaKind,
     category_id: String,
     name: String,
     url: String,
+    extract: Option<ExtractRule>,
+    kind: Option<ResponseKind>,
 ) -> Result<ApiEndpoint, String> {
-    let name = name.trim();
-    let url = url.trim();
-
-    if name.is_empty() || url.is_empty() {
-        return Err("名称和URL不能为空".into());
-    }
-
-    // 验证分类存在（不能是 random）
-    if category_id == RANDOM_CATEGORY || !category_exists(&state, &category_id) {
-        return Err("分类不存在".into());
-    }
-
-    let endpoint = ApiEndpoint {
-        id: format!("custom_ep_{}", now_millis()),
-        name: name.into(),
-        url: url.into(),
-        builtin: false,
-    };
-
-    state
-        .custom_endpoints
-        .lock()
-        .unwrap()
-        .push((category_id, endpoint.clone()));
-
-    Ok(endpoint)
+    state.update_catalog(media, |catalog| {
+        catalog.add_endpoint(
+            media.spec(),
+            category_id,
+            &name,
+            &url,
+            extract,
+            kind.unwrap_or_default(),
+        )
+    })
 }
 
 #[tauri::command]
-fn delete_custom_api(state: State<'_, AppState>, api_id: String) -> Result<(), String> {
-    let mut endpoints = state.custom_endpoints.lock().unwrap();
-    let len_before = endpoints.len();
-    endpoints.retain(|(_, ep)| ep.id != api_id);
+fn delete_custom_api(
+    state: State<'_, Arc<Engine>>,
+    media: MediaKind,
+    api_id: String,
+) -> Result<(), String> {
+    state.update_catalog(media, |catalog| {
+        catalog.delete_endpoint(media.spec(), &api_id)
+    })
+}
 
-    if endpoints.len() == len_before {
-        return Err("未找到该接口".into());
-    }
-    drop(endpoints);
-
-    // 检查当前分类是否还有接口，没有则回退到随机
-    let current = state.current_category.lock().unwrap().clone();
-    if current != RANDOM_CATEGORY {
-        let categories = get_all_categories(&state);
-        let has_endpoints = categories
-            .iter()
-            .find(|c| c.id == current)
-            .map(|c| !c.endpoints.is_empty())
-            .unwrap_or(false);
-
-        if !has_endpoints {
-            *state.current_category.lock().unwrap() = RANDOM_CATEGORY.into();
-        }
-    }
+#[tauri::command]
+fn delete_custom_category(
+    state: State<'_, Arc<Engine>>,
+    media: MediaKind,
+    category_id: String,
+) -> Result<(), String> {
+    state.update_catalog(media, |catalog| {
+        catalog.delete_category(media.spec(), &category_id)
+    })
+}
 
-    Ok(())
+#[tauri::command]
+fn export_api_catalog(state: State<'_, Arc<Engine>>, path: String) -> Result<(), String> {
+    state.export_catalog(Path::new(path.trim()))
 }
 
 #[tauri::command]
-fn delete_custom_category(state: State<'_, AppState>, category_id: String) -> Result<(), String> {
-    // 不能删除内置分类
-    if get_builtin_categories().iter().any(|c| c.id == category_id) {
-        return Err("无法删除内置分类".into());
-    }
+fn import_api_catalog(
+    state: State<'_, Arc<Engine>>,
+    path: String,
+    mode: ImportMode,
+) -> Result<ImportSummary, String> {
+    state.import_catalog(Path::new(path.trim()), mode)
+}
 
-    let mut categories = state.custom_categories.lock().unwrap();
-    let len_before = categories.len();
-    categories.retain(|c| c.id != category_id);
+#[tauri::command]
+fn get_library_dir(state: State<'_, Arc<Engine>>) -> String {
+    state.favorites.dir().display().to_string()
+}
 
-    if categories.len() == len_before {
-        return Err("未找到该分类".into());
+#[tauri::command]
+fn set_library_dir(state: State<'_, Arc<Engine>>, path: String) -> Result<(), String> {
+    let path = path.trim();
+    if path.is_empty() {
+        return Err("收藏目录不能为空".into());
     }
+    state.set_library_dir(PathBuf::from(path))
+}
 
-    // 删除该分类下的所有接口
-    state
-        .custom_endpoints
-        .lock()
-        .unwrap()
-        .retain(|(cat_id, _)| cat_id != &category_id);
+/// 把当前播放的视频保存到收藏库
+#[tauri::command]
+async fn save_current_video(state: State<'_, Arc<Engine>>) -> Result<Favorite, String> {
+    state.save_current_video().await
+}
 
-    // 如果当前选中的是被删除的分类，切换到随机
-    let mut current = state.current_category.lock().unwrap();
-    if *current == category_id {
-        *current = RANDOM_CATEGORY.into();
-    }
+/// 把当前显示的图片保存到收藏库
+#[tauri::command]
+async fn save_current_image(state: State<'_, Arc<Engine>>) -> Result<Favorite, String> {
+    state.save_current_image().await
+}
 
-    Ok(())
+/// 收藏列表，最新的在前；`tag` 为空时不按标签过滤
+#[tauri::command]
+fn list_favorites(
+    state: State<'_, Arc<Engine>>,
+    media: Option<MediaKind>,
+    tag: Option<String>,
+) -> Vec<Favorite> {
+    let tag = tag.as_deref().map(str::trim).filter(|t| !t.is_empty());
+    state.favorites.list(media, tag)
 }
 
 #[tauri::command]
-fn get_image_categories(state: State<'_, AppState>) -> Vec<ApiCategory> {
-    get_all_image_categories(&state)
+fn set_favorite_tags(
+    state: State<'_, Arc<Engine>>,
+    id: String,
+    tags: Vec<String>,
+) -> Result<Favorite, String> {
+    state.favorites.set_tags(&id, tags)
 }
 
+/// 收藏中互相相似的图片分组，按设置中的感知哈希与汉明距离判断
 #[tauri::command]
-fn get_current_image_category(state: State<'_, AppState>) -> String {
-    state.current_image_category.lock().unwrap().clone()
+async fn list_similar_favorites(
+    state: State<'_, Arc<Engine>>,
+) -> Result<Vec<Vec<Favorite>>, String> {
+    Ok(state.similar_favorites().await)
 }
 
+/// 删除收藏的文件与元数据
 #[tauri::command]
-fn set_current_image_category(state: State<'_, AppState>, category_id: String) -> Result<(), String> {
-    if !image_category_exists(&state, &category_id) {
-        return Err("分类不存在".into());
-    }
-    *state.current_image_category.lock().unwrap() = category_id;
-    Ok(())
+fn remove_favorite(state: State<'_, Arc<Engine>>, id: String) -> Result<(), String> {
+    state.favorites.remove(&id)
 }
 
+/// 收藏的文件经 `stream:///favorite/{id}` 读取，不需要联网
 #[tauri::command]
-fn add_custom_image_category(state: State<'_, AppState>, name: String) -> Result<ApiCategory, String> {
-    let name = name.trim();
-    if name.is_empty() {
-        return Err("分类名称不能为空".into());
-    }
+fn play_favorite(state: State<'_, Arc<Engine>>, id: String) -> Result<MediaSource, String> {
+    let favorite = state
+        .favorites
+        .get(&id)
+        .ok_or_else(|| "收藏不存在".to_string())?;
+    let url = format!("stream:///favorite/{}", favorite.id);
+    Ok(MediaSource {
+        url: url.clone(),
+        stream_url: url,
+    })
+}
 
-    let category = ApiCategory {
-        id: format!("custom_img_cat_{}", now_millis()),
-        name: name.into(),
-        builtin: false,
-        endpoints: vec![],
-    };
+// ============================================================
+// Stream 协议
+// ============================================================
 
-    state
-        .custom_image_categories
-        .lock()
+type StreamResponse = tauri::http::Response<Vec<u8>>;
+
+/// 播放器请求中与区间有关的头
+#[derive(Clone)]
+struct RangeHeaders {
+    range: Option<String>,
+    if_range: Option<String>,
+}
+
+fn empty_response(status: u16) -> StreamResponse {
+    tauri::http::Response::builder()
+        .status(status)
+        .body(Vec::new())
         .unwrap()
-        .push(category.clone());
-    Ok(category)
 }
 
-#[tauri::command]
-fn add_custom_image_api(
-    state: State<'_, AppState>,
-    category_id: String,
-    name: String,
-    url: String,
-) -> Result<ApiEndpoint, String> {
-    let name = name.trim();
-    let url = url.trim();
+/// 读取正在播放的预加载视频，可能等待下载，需在阻塞线程中调用
+fn serve_preloaded(
+    engine: &Engine,
+    video_id: &str,
+    range: &RangeHeaders,
+) -> Option<StreamResponse> {
+    let result = engine.preload.read_playing(
+        video_id,
+        range.range.as_deref(),
+        range.if_range.as_deref(),
+        STREAM_WAIT_TIMEOUT,
+    )?;
+
+    match result {
+        Ok(reply) => Some(reply_response(reply)),
+        Err(e) => {
+            println!("Stream: 读取预加载视频失败: {}", e);
+            Some(empty_response(500))
+        }
+    }
+}
 
-    if name.is_empty() || url.is_empty() {
-        return Err("名称和URL不能为空".into());
+fn reply_response(reply: StreamReply) -> StreamResponse {
+    let mut builder = tauri::http::Response::builder().status(reply.status);
+    for (name, value) in reply.headers {
+        builder = builder.header(name, value);
     }
+    builder.body(reply.body).unwrap()
+}
 
-    if category_id == RANDOM_CATEGORY || !image_category_exists(&state, &category_id) {
-        return Err("分类不存在".into());
+/// `stream:///hls/{session}/{n}`：播放列表中改写过的分片、密钥等资源
+async fn serve_hls_resource(engine: &Engine, path: &str, range: &RangeHeaders) -> StreamResponse {
+    let resource = path.split_once('/').and_then(|(session_id, index)| {
+        let index = index.parse().ok()?;
+        engine.hls_session(session_id)?.resource(index)
+    });
+    let url = match resource {
+        Some(url) => url,
+        None => return empty_response(404),
+    };
+
+    match engine
+        .hls_resource(&url, range.range.as_deref(), range.if_range.as_deref())
+        .await
+    {
+        Ok(reply) => reply_response(reply),
+        Err(e) => {
+            println!("Stream: 读取 HLS 分片失败: {}", e);
+            empty_response(500)
+        }
     }
+}
 
-    let endpoint = ApiEndpoint {
-        id: format!("custom_img_ep_{}", now_millis()),
-        name: name.into(),
-        url: url.into(),
-        builtin: false,
+/// `stream:///remote/{id}`：按窗口代理已登记的远程视频，HLS 返回改写后的播放列表
+async fn serve_remote(engine: &Engine, id: &str, range: &RangeHeaders) -> StreamResponse {
+    let video = match engine.remote.get(id) {
+        Some(video) => video,
+        None => return empty_response(404),
     };
+    if let Some(session) = &video.hls {
+        return reply_response(engine.hls_manifest(session).await);
+    }
 
-    state
-        .custom_image_endpoints
-        .lock()
-        .unwrap()
-        .push((category_id, endpoint.clone()));
+    let req = engine.proxy_request(&video.url);
+    let resp = match fetch_window(req, range.range.as_deref(), range.if_range.as_deref()).await {
+        Ok(resp) => resp,
+        Err(e) => {
+            println!("Proxy: {}", e);
+            return empty_response(500);
+        }
+    };
 
-    Ok(endpoint)
+    let content_type = video.content_type(&resp);
+    println!(
+        "Proxy: id={}, status={}, type={}, range={:?}, size={}",
+        video.id,
+        resp.status,
+        content_type,
+        resp.content_range,
+        resp.data.len()
+    );
+
+    reply_response(resp.into_reply(content_type))
 }
 
-#[tauri::command]
-fn delete_custom_image_api(state: State<'_, AppState>, api_id: String) -> Result<(), String> {
-    let mut endpoints = state.custom_image_endpoints.lock().unwrap();
-    let len_before = endpoints.len();
-    endpoints.retain(|(_, ep)| ep.id != api_id);
+/// `stream:///image/{id}`：首次请求时下载到磁盘缓存，之后直接读取缓存
+async fn serve_image(engine: &Engine, id: &str, range: &RangeHeaders) -> StreamResponse {
+    let image = match engine.images.get(id) {
+        Some(image) => image,
+        None => return empty_response(404),
+    };
 
-    if endpoints.len() == len_before {
-        return Err("未找到该接口".into());
-    }
-    drop(endpoints);
-
-    let current = state.current_image_category.lock().unwrap().clone();
-    if current != RANDOM_CATEGORY {
-        let categories = get_all_image_categories(&state);
-        let has_endpoints = categories
-            .iter()
-            .find(|c| c.id == current)
-            .map(|c| !c.endpoints.is_empty())
-            .unwrap_or(false);
-
-        if !has_endpoints {
-            *state.current_image_category.lock().unwrap() = RANDOM_CATEGORY.into();
+    match engine
+        .read_image(&image, range.range.as_deref(), range.if_range.as_deref())
+        .await
+    {
+        Ok(reply) => reply_response(reply),
+        Err(e) => {
+            println!("Stream: 读取图片失败: {}", e);
+            empty_response(502)
         }
     }
+}
 
-    Ok(())
+fn serve_favorite(engine: &Engine, id: &str, range: &RangeHeaders) -> StreamResponse {
+    match engine.read_favorite(id, range.range.as_deref(), range.if_range.as_deref()) {
+        Some(Ok(reply)) => reply_response(reply),
+        Some(Err(e)) => {
+            println!("Stream: 读取收藏失败: {}", e);
+            empty_response(500)
+        }
+        None => empty_response(404),
+    }
 }
 
-#[tauri::command]
-fn delete_custom_image_category(
-    state: State<'_, AppState>,
-    category_id: String,
-) -> Result<(), String> {
-    if get_builtin_image_categories()
-        .iter()
-        .any(|c| c.id == category_id)
-    {
-        return Err("无法删除内置分类".into());
+async fn handle_stream(app: AppHandle, request: tauri::http::Request<Vec<u8>>) -> StreamResponse {
+    let path = request.uri().path().to_string();
+    let host = request.uri().host().unwrap_or_default().to_string();
+    let header = |name: &str| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string())
+    };
+    let range = RangeHeaders {
+        range: header("range"),
+        if_range: header("if-range"),
+    };
+
+    if let Some(resource) = path.strip_prefix("/hls/") {
+        return serve_hls_resource(&app.state::<Arc<Engine>>(), resource, &range).await;
     }
 
-    let mut categories = state.custom_image_categories.lock().unwrap();
-    let len_before = categories.len();
-    categories.retain(|c| c.id != category_id);
+    // stream:///video/{id} 或兼容 stream://video/{id}
+    let video_id = path
+        .strip_prefix("/video/")
+        .or_else(|| {
+            if host == "video" {
+                path.strip_prefix('/')
+            } else {
+                None
+            }
+        })
+        .map(|id| id.to_string());
+
+    if let Some(video_id) = video_id {
+        let state = app.state::<Arc<Engine>>();
+        if let Some(session) = state.hls_session(&video_id) {
+            return reply_response(state.hls_manifest(&session).await);
+        }
 
-    if categories.len() == len_before {
-        return Err("未找到该分类".into());
+        let preload_app = app.clone();
+        let preload_range = range.clone();
+        let served = tauri::async_runtime::spawn_blocking(move || {
+            serve_preloaded(
+                &preload_app.state::<Arc<Engine>>(),
+                &video_id,
+                &preload_range,
+            )
+        })
+        .await;
+
+        match served {
+            Ok(Some(response)) => return response,
+            Ok(None) => {}
+            Err(e) => {
+                println!("Stream: 预加载读取任务失败: {}", e);
+                return empty_response(500);
+            }
+        }
     }
 
-    state
-        .custom_image_endpoints
-        .lock()
-        .unwrap()
-        .retain(|(cat_id, _)| cat_id != &category_id);
+    if let Some(id) = path.strip_prefix("/remote/") {
+        return serve_remote(&app.state::<Arc<Engine>>(), id, &range).await;
+    }
 
-    let mut current = state.current_image_category.lock().unwrap();
-    if *current == category_id {
-        *current = RANDOM_CATEGORY.into();
+    if let Some(id) = path.strip_prefix("/image/") {
+        return serve_image(&app.state::<Arc<Engine>>(), id, &range).await;
     }
 
-    Ok(())
+    if let Some(id) = path.strip_prefix("/favorite/") {
+        let id = id.to_string();
+        let favorite_app = app.clone();
+        return tauri::async_runtime::spawn_blocking(move || {
+            serve_favorite(&favorite_app.state::<Arc<Engine>>(), &id, &range)
+        })
+        .await
+        .unwrap_or_else(|e| {
+            println!("Stream: 收藏读取任务失败: {}", e);
+            empty_response(500)
+        });
+    }
+
+    println!("Stream: 未知的请求 path: {}, host: {}", path, host);
+    empty_response(404)
 }
 
 // ============================================================
-// Main
+// 后台预加载
 // ============================================================
 
-fn parse_range(header: &str, total_len: usize) -> Option<(usize, usize)> {
-    let header = header.strip_prefix("bytes=")?;
-    let mut parts = header.split('-');
-    let start = parts.next()?.parse::<usize>().ok()?;
-    let end = parts
-        .next()
-        .and_then(|s| if s.is_empty() { None } else { s.parse::<usize>().ok() })
-        .unwrap_or(total_len.saturating_sub(1));
-    if start <= end && end < total_len {
-        Some((start, end))
-    } else {
-        None
-    }
+/// 持续补充视频预加载队列，入队与失败分别发出 `preload://added` 与 `preload://failed`
+async fn run_preload_scheduler(app: AppHandle) {
+    let engine = app.state::<Arc<Engine>>().inner().clone();
+    engine
+        .scheduler
+        .run(engine.clone(), move |event| {
+            let name = match event {
+                PreloadEvent::Added { .. } => "preload://added",
+                PreloadEvent::Failed { .. } => "preload://failed",
+            };
+            if let Err(e) = app.emit(name, &event) {
+                println!("Preload: 发送事件失败: {}", e);
+            }
+        })
+        .await;
 }
 
+// ============================================================
+// Main
+// ============================================================
+
 fn main() {
+    let context = tauri::generate_context!();
+    let identifier = &context.config().identifier;
+    // 调度器的下载任务各自持有一份引用
+    let engine = Arc::new(Engine::load(
+        settings_path(identifier),
+        app_cache_dir(identifier),
+    ));
+    // 等前端开始播放后再恢复，避免与第一个视频争抢带宽
+    engine.scheduler.set_paused(true);
+
     tauri::Builder::default()
-        .manage(AppState {
-            current_video_url: Mutex::new(None),
-            current_category: Mutex::new(RANDOM_CATEGORY.into()),
-            custom_categories: Mutex::new(Vec::new()),
-            custom_endpoints: Mutex::new(Vec::new()),
-            current_image_category: Mutex::new(RANDOM_CATEGORY.into()),
-            custom_image_categories: Mutex::new(Vec::new()),
-            custom_image_endpoints: Mutex::new(Vec::new()),
-            preload_queue: Mutex::new(VecDeque::new()),
-            playing_video: Mutex::new(None),
-            preload_in_progress: AtomicBool::new(false),
-        })
+        .manage(engine)
         .plugin(tauri_plugin_shell::init())
-        .register_uri_scheme_protocol("stream", |ctx, request| {
-            let state: State<AppState> = ctx.app_handle().state();
-            let path = request.uri().path();
-            let host = request.uri().host().unwrap_or_default();
-
-            // stream:///video/{id} 或兼容 stream://video/{id}
-            let video_id = path
-                .strip_prefix("/video/")
-                .or_else(|| if host == "video" { path.strip_prefix('/') } else { None });
-
-            if let Some(video_id) = video_id {
-                let playing = state.playing_video.lock().unwrap();
-                if let Some(ref video) = *playing {
-                    if video.id == video_id {
-                        let range_header = request
-                            .headers()
-                            .get("range")
-                            .and_then(|v| v.to_str().ok())
-                            .map(|s| s.to_string());
-
-                        let data = &video.data;
-                        let total_len = data.len();
-
-                        if let Some(range_str) = range_header {
-                            if let Some((start, end)) = parse_range(&range_str, total_len) {
-                                let slice = data.slice(start..=end);
-                                return tauri::http::Response::builder()
-                                    .status(206)
-                                    .header("Content-Type", "video/mp4")
-                                    .header("Accept-Ranges", "bytes")
-                                    .header("Content-Length", slice.len().to_string())
-                                    .header("Content-Range", format!("bytes {}-{}/{}", start, end, total_len))
-                                    .body(slice.to_vec())
-                                    .unwrap();
-                            }
-                        }
-
-                        return tauri::http::Response::builder()
-                            .status(200)
-                            .header("Content-Type", "video/mp4")
-                            .header("Accept-Ranges", "bytes")
-                            .header("Content-Length", total_len.to_string())
-                            .body(data.to_vec())
-                            .unwrap();
-                    }
-                }
-                drop(playing);
-            }
-
-            // Fallback: 原有的代理逻辑
-            let url_lock = state.current_video_url.lock().unwrap();
-            let incoming_range = request
-                .headers()
-                .get("range")
-                .and_then(|v| v.to_str().ok())
-                .map(|s| s.to_string());
-            println!("Stream request - path: {}, host: {}, range: {:?}, url: {:?}",
-                path, host, incoming_range, url_lock.as_ref());
-
-            if let Some(video_url) = url_lock.as_ref() {
-                let video_url = video_url.clone();
-                drop(url_lock);
-
-                let range_header = request
-                    .headers()
-                    .get("range")
-                    .and_then(|v| v.to_str().ok())
-                    .map(|s| s.to_string());
-
-                let rt = tokio::runtime::Runtime::new().unwrap();
-                let result = rt.block_on(async {
-                    let client = build_client();
-                    let mut req = client
-                        .get(&video_url)
-                        .header(REFERER, "https://api.tzjsy.cn/");
-
-                    if let Some(range) = &range_header {
-                        req = req.header(RANGE, range);
-                    }
-
-                    req.send().await
-                });
-
-                match result {
-                    Ok(resp) => {
-                        let status = resp.status();
-                        let content_type = resp
-                            .headers()
-                            .get(CONTENT_TYPE)
-                            .and_then(|v| v.to_str().ok())
-                            .unwrap_or("video/mp4")
-                            .to_string();
-                        let content_range = resp
-                            .headers()
-                            .get(CONTENT_RANGE)
-                            .and_then(|v| v.to_str().ok())
-                            .map(|s| s.to_string());
-
-                        println!("Proxy: status={}, type={}, range={:?}", status, content_type, content_range);
-
-                        let body = rt.block_on(async { resp.bytes().await });
-
-                        match body {
-                            Ok(bytes) => {
-                                println!("Proxy: body size = {} bytes", bytes.len());
-                                let mut builder = tauri::http::Response::builder()
-                                    .status(status.as_u16())
-                                    .header("Content-Type", content_type)
-                                    .header("Accept-Ranges", "bytes")
-                                    .header("Content-Length", bytes.len().to_string());
-
-                                if let Some(range) = content_range {
-                                    builder = builder.header("Content-Range", range);
-                                }
-
-                                builder.body(bytes.to_vec()).unwrap()
-                            }
-                            Err(e) => {
-                                println!("Proxy: body read error: {}", e);
-                                tauri::http::Response::builder()
-                                    .status(500)
-                                    .body(Vec::new())
-                                    .unwrap()
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        println!("Proxy: request error: {}", e);
-                        tauri::http::Response::builder()
-                            .status(500)
-                            .body(Vec::new())
-                            .unwrap()
-                    }
-                }
-            } else {
-                tauri::http::Response::builder()
-                    .status(404)
-                    .body(Vec::new())
-                    .unwrap()
-            }
+        .setup(|app| {
+            tauri::async_runtime::spawn(run_preload_scheduler(app.handle().clone()));
+            Ok(())
+        })
+        .register_asynchronous_uri_scheme_protocol("stream", |ctx, request, responder| {
+            let app = ctx.app_handle().clone();
+            tauri::async_runtime::spawn(async move {
+                responder.respond(handle_stream(app, request).await);
+            });
         })
         .invoke_handler(tauri::generate_handler![
             fetch_video,
@@ -1372,6 +666,23 @@ fn main() {
             get_preload_count,
             pop_next_video,
             clear_preload_queue,
+            set_preload_paused,
+            history_back,
+            history_forward,
+            replay_history,
+            search_history,
+            clear_history,
+            list_preload_queue,
+            remove_preloaded_video,
+            move_preloaded_to_front,
+            pin_preloaded_video,
+            preload_next_image,
+            get_image_preload_count,
+            pop_next_image,
+            peek_next_image,
+            clear_image_preload_queue,
+            get_preload_settings,
+            set_preload_settings,
             get_categories,
             get_current_category,
             set_current_category,
@@ -1379,14 +690,18 @@ fn main() {
             add_custom_api,
             delete_custom_api,
             delete_custom_category,
-            get_image_categories,
-            get_current_image_category,
-            set_current_image_category,
-            add_custom_image_category,
-            add_custom_image_api,
-            delete_custom_image_api,
-            delete_custom_image_category
+            export_api_catalog,
+            import_api_catalog,
+            get_library_dir,
+            set_library_dir,
+            save_current_video,
+            save_current_image,
+            list_favorites,
+            set_favorite_tags,
+            list_similar_favorites,
+            remove_favorite,
+            play_favorite
         ])
-        .run(tauri::generate_context!())
+        .run(context)
         .expect("error while running tauri application");
 }
@@ -2,12 +2,30 @@ const video = document.getElementById('video');
 const videoWrapper = document.getElementById('videoWrapper');
 const loading = document.getElementById('loading');
 const nextBtn = document.getElementById('nextBtn');
+const prevBtn = document.getElementById('prevBtn');
 const autoPlayCheckbox = document.getElementById('autoPlay');
 const status = document.getElementById('status');
 const image = document.getElementById('image');
 const imageLoading = document.getElementById('imageLoading');
 const nextImageBtn = document.getElementById('nextImageBtn');
 const imageStatus = document.getElementById('imageStatus');
+const upNext = document.getElementById('upNext');
+const upNextList = document.getElementById('upNextList');
+const historyBtn = document.getElementById('historyBtn');
+const historyModal = document.getElementById('historyModal');
+const closeHistory = document.getElementById('closeHistory');
+const historySearch = document.getElementById('historySearch');
+const historyList = document.getElementById('historyList');
+const clearHistoryBtn = document.getElementById('clearHistoryBtn');
+const saveVideoBtn = document.getElementById('saveVideoBtn');
+const saveImageBtn = document.getElementById('saveImageBtn');
+const favoritesBtn = document.getElementById('favoritesBtn');
+const favoritesModal = document.getElementById('favoritesModal');
+const closeFavorites = document.getElementById('closeFavorites');
+const favoritesMedia = document.getElementById('favoritesMedia');
+const favoritesTag = document.getElementById('favoritesTag');
+const favoritesList = document.getElementById('favoritesList');
+const similarFavoritesBtn = document.getElementById('similarFavoritesBtn');
 const tabButtons = document.querySelectorAll('.tab-btn');
 const panels = document.querySelectorAll('.panel');
 
@@ -28,10 +46,28 @@ const categorySelect = document.getElementById('categorySelect');
 const newCategoryName = document.getElementById('newCategoryName');
 const newApiName = document.getElementById('newApiName');
 const newApiUrl = document.getElementById('newApiUrl');
+const newApiKind = document.getElementById('newApiKind');
+const newApiPath = document.getElementById('newApiPath');
+const newApiSuccess = document.getElementById('newApiSuccess');
+const newApiRegex = document.getElementById('newApiRegex');
 const addApiBtn = document.getElementById('addApiBtn');
 const clearCacheBtn = document.getElementById('clearCacheBtn');
 const settingsTitle = document.getElementById('settingsTitle');
 const settingsTabs = document.querySelectorAll('.settings-tab');
+const catalogPath = document.getElementById('catalogPath');
+const importMode = document.getElementById('importMode');
+const exportCatalogBtn = document.getElementById('exportCatalogBtn');
+const importCatalogBtn = document.getElementById('importCatalogBtn');
+const videoDepthInput = document.getElementById('videoDepthInput');
+const imageDepthInput = document.getElementById('imageDepthInput');
+const concurrencyInput = document.getElementById('concurrencyInput');
+const budgetInput = document.getElementById('budgetInput');
+const dedupWindowInput = document.getElementById('dedupWindowInput');
+const imageHashSelect = document.getElementById('imageHashSelect');
+const imageDistanceInput = document.getElementById('imageDistanceInput');
+const savePreloadBtn = document.getElementById('savePreloadBtn');
+const libraryDirInput = document.getElementById('libraryDirInput');
+const saveLibraryDirBtn = document.getElementById('saveLibraryDirBtn');
 
 let isLoading = false;
 let currentVideoSrc = null;
@@ -40,8 +76,7 @@ let autoSkipTimer = null;
 let consecutiveErrors = 0;
 const RETRY_BASE_MS = 500;
 const RETRY_MAX_MS = 2000;
-const PRELOAD_INTERVAL_MS = 5000;
-const PRELOAD_MIN_BUFFER_SEC = 10;
+const MB = 1024 * 1024;
 let activePanel = 'video';
 let settingsMode = 'video';
 let isImageLoading = false;
@@ -62,10 +97,6 @@ function revokeObjectUrlIfNeeded(url) {
   }
 }
 
-function makeStreamUrl() {
-  return `stream:///video?ts=${Date.now()}`;
-}
-
 function clearAutoSkipTimer() {
   if (autoSkipTimer) {
     clearTimeout(autoSkipTimer);
@@ -98,14 +129,14 @@ function setActivePanel(panelId) {
     if (!video.paused) {
       video.pause();
     }
-    stopPreloadLoop();
+    pausePreload();
     if (!imageInitialized) {
       initImage();
       imageInitialized = true;
     }
   } else {
     if (!video.paused) {
-      startPreloadLoop();
+      resumePreload();
     }
   }
 }
@@ -122,13 +153,13 @@ playPauseBtn.addEventListener('click', () => {
 video.addEventListener('play', () => {
   playPauseBtn.textContent = '⏸';
   if (activePanel === 'video') {
-    startPreloadLoop();
+    resumePreload();
   }
 });
 
 video.addEventListener('pause', () => {
   playPauseBtn.textContent = '▶';
-  stopPreloadLoop();
+  pausePreload();
 });
 
 video.addEventListener('timeupdate', () => {
@@ -188,75 +219,139 @@ async function playVideoEl() {
   }
 }
 
-async function fetchVideoUrl() {
-  const videoUrl = await window.__TAURI__.core.invoke('fetch_video');
-  console.log('Fetching video:', videoUrl);
-  return videoUrl;
+// 返回 { url, streamUrl }：直链与经本地代理播放的地址
+async function fetchVideoSource() {
+  const source = await window.__TAURI__.core.invoke('fetch_video');
+  console.log('Fetching video:', source.url);
+  return source;
 }
 
-function triggerPreload() {
-  return window.__TAURI__.core.invoke('preload_next').catch(() => {});
+// 预加载由后端调度器自动补满，只在窗口可见且正在播放视频时运行
+let preloadEnabled = false;
+
+function syncPreloadPaused() {
+  const paused = !preloadEnabled || activePanel !== 'video' || document.hidden;
+  return window.__TAURI__?.core?.invoke('set_preload_paused', { paused }).catch(() => {});
 }
 
-let preloadInterval = null;
-let preloadInFlight = false;
-let preloadEnabled = false;
+function resumePreload() {
+  preloadEnabled = true;
+  syncPreloadPaused();
+}
 
-function getBufferedAheadSeconds() {
-  try {
-    const buffered = video.buffered;
-    const current = video.currentTime;
-    for (let i = 0; i < buffered.length; i += 1) {
-      const start = buffered.start(i);
-      const end = buffered.end(i);
-      if (current >= start && current <= end) {
-        return Math.max(end - current, 0);
-      }
-    }
-  } catch {}
-  return 0;
+function pausePreload() {
+  preloadEnabled = false;
+  syncPreloadPaused();
 }
 
-function shouldPreload() {
-  if (!preloadEnabled || activePanel !== 'video') return false;
-  if (video.paused || video.readyState < 3) return false;
-  return getBufferedAheadSeconds() >= PRELOAD_MIN_BUFFER_SEC;
+document.addEventListener('visibilitychange', syncPreloadPaused);
+
+function subscribePreloadEvents() {
+  const listen = window.__TAURI__?.event?.listen;
+  if (!listen) return;
+  listen('preload://added', (event) => {
+    console.log(`Preloaded (${event.payload.queueLen} queued):`, event.payload.url);
+    renderUpNext();
+  });
+  listen('preload://failed', (event) => {
+    const { error, failures, retryInMs } = event.payload;
+    console.warn(`Preload failed (${failures}x), retry in ${retryInMs}ms:`, error);
+  });
 }
 
-async function runPreloadTick() {
-  if (!shouldPreload() || preloadInFlight) return;
-  preloadInFlight = true;
+async function tryPopPreloaded() {
   try {
-    await triggerPreload();
+    return await window.__TAURI__.core.invoke('pop_next_video');
+  } catch {
+    return null;
   } finally {
-    preloadInFlight = false;
+    renderUpNext();
   }
 }
 
-function startPreloadLoop() {
-  if (preloadInterval) return;
-  preloadEnabled = true;
-  runPreloadTick();
-  preloadInterval = setInterval(runPreloadTick, PRELOAD_INTERVAL_MS);
+// ============================================================
+// 接下来播放
+// ============================================================
+
+const UP_NEXT_REFRESH_MS = 3000;
+
+function formatSize(bytes) {
+  return `${(bytes / MB).toFixed(1)}MB`;
 }
-function stopPreloadLoop() {
-  preloadEnabled = false;
-  if (preloadInterval) {
-    clearInterval(preloadInterval);
-    preloadInterval = null;
+
+function describeQueueEntry(entry) {
+  if (entry.error) return `失败: ${entry.error}`;
+  if (entry.size === null) {
+    return entry.downloaded > 0 ? `已下载 ${formatSize(entry.downloaded)}` : entry.contentType;
   }
+  if (entry.finished) return `${entry.contentType} · ${formatSize(entry.size)}`;
+  const percent = entry.size > 0 ? Math.floor((entry.downloaded / entry.size) * 100) : 0;
+  return `${entry.contentType} · ${formatSize(entry.size)} · ${percent}%`;
 }
 
-async function tryPopPreloaded() {
+function queueButton(text, title, onClick) {
+  const btn = document.createElement('button');
+  btn.textContent = text;
+  btn.title = title;
+  btn.addEventListener('click', async () => {
+    try {
+      await onClick();
+    } catch (err) {
+      console.warn(`${title} failed:`, err);
+    }
+    renderUpNext();
+  });
+  return btn;
+}
+
+async function renderUpNext() {
+  if (!window.__TAURI__?.core?.invoke) return;
+  let entries;
   try {
-    const count = await window.__TAURI__.core.invoke('get_preload_count');
-    if (count > 0) {
-      return await window.__TAURI__.core.invoke('pop_next_video');
+    entries = await window.__TAURI__.core.invoke('list_preload_queue');
+  } catch (err) {
+    console.warn('list_preload_queue failed:', err);
+    return;
+  }
+
+  const invoke = window.__TAURI__.core.invoke;
+  upNext.classList.toggle('hidden', entries.length === 0);
+  upNextList.replaceChildren(...entries.map((entry, index) => {
+    const item = document.createElement('li');
+    item.className = 'up-next-item';
+    item.classList.toggle('pinned', entry.pinned);
+
+    const info = document.createElement('div');
+    info.className = 'up-next-info';
+    const name = document.createElement('span');
+    name.className = 'up-next-name';
+    name.textContent = entry.endpointName;
+    const meta = document.createElement('span');
+    meta.className = 'up-next-meta';
+    meta.textContent = describeQueueEntry(entry);
+    meta.title = entry.url;
+    info.append(name, meta);
+    item.append(info);
+
+    if (index > 0) {
+      item.append(queueButton('⤒', '下一个播放', () => invoke('move_preloaded_to_front', { id: entry.id })));
     }
-  } catch {}
-  return null;
+    item.append(
+      queueButton(entry.pinned ? '★' : '☆', entry.pinned ? '取消固定' : '固定（切换分类时保留）',
+        () => invoke('pin_preloaded_video', { id: entry.id, pinned: !entry.pinned })),
+      queueButton('×', '移除', () => invoke('remove_preloaded_video', { id: entry.id })),
+    );
+    return item;
+  }));
 }
 
+// 下载进度只在界面可见时刷新
+setInterval(() => {
+  if (activePanel === 'video' && !document.hidden) {
+    renderUpNext();
+  }
+}, UP_NEXT_REFRESH_MS);
+
 function waitForVideoReady(timeoutMs = 30000) {
   return new Promise((resolve, reject) => {
     let settled = false;
@@ -304,16 +399,36 @@ async function setVideoSource(sourceUrl) {
   await waitForVideoReady();
 }
 
-async function fetchImageUrl() {
-  const imageUrl = await window.__TAURI__.core.invoke('fetch_image');
-  console.log('Fetching image:', imageUrl);
-  return imageUrl;
+// 先尝试直接播放，失败时改走本地代理
+async function playSource(source) {
+  try {
+    currentVideoSrc = source.url;
+    await setVideoSource(source.url);
+  } catch (err) {
+    console.warn('Direct video load failed, using stream proxy:', err);
+    currentVideoSrc = source.streamUrl;
+    await setVideoSource(source.streamUrl);
+  }
+}
+
+// 返回 { url, streamUrl }：直链与经本地缓存提供的地址
+async function fetchImageSource() {
+  const source = await window.__TAURI__.core.invoke('fetch_image');
+  console.log('Fetching image:', source.url);
+  return source;
 }
 
-async function fetchImageAsBlobByUrl(imageUrl) {
-  const data = await window.__TAURI__.core.invoke('download_image', { url: imageUrl });
-  const blob = new Blob([new Uint8Array(data)]);
-  return URL.createObjectURL(blob);
+function triggerImagePreload() {
+  return window.__TAURI__.core.invoke('preload_next_image').catch(() => {});
+}
+
+// 预加载的图片已在本地缓存，直接使用 stream 地址
+async function tryPopPreloadedImage() {
+  try {
+    const source = await window.__TAURI__.core.invoke('pop_next_image');
+    return { url: source.streamUrl, streamUrl: source.streamUrl };
+  } catch {}
+  return null;
 }
 
 function setImageSource(sourceUrl) {
@@ -334,45 +449,55 @@ function setImageSource(sourceUrl) {
   });
 }
 
-async function loadVideo() {
+// 后退过时先沿播放记录前进，回到最新一条后再取预加载或新解析的视频
+async function nextVideoSource() {
+  const forward = await window.__TAURI__.core.invoke('history_forward');
+  if (forward) return forward;
+  const preloaded = await tryPopPreloaded();
+  return preloaded || await fetchVideoSource();
+}
+
+// `loadSource` 返回 null 时保持当前播放
+async function loadVideo(loadSource = nextVideoSource) {
   if (isLoading) return;
-  stopPreloadLoop();
+  pausePreload();
   clearAutoSkipTimer();
   isLoading = true;
   nextBtn.disabled = true;
+  prevBtn.disabled = true;
   status.classList.remove('error');
   const version = cacheVersion;
   let shouldAutoSkip = false;
 
   try {
-    revokeObjectUrlIfNeeded(currentVideoSrc);
-    currentVideoSrc = null;
     loading.classList.remove('hidden');
     status.textContent = '加载中...';
 
-    const preloaded = await tryPopPreloaded();
-    const directUrl = preloaded || await fetchVideoUrl();
+    const source = await loadSource();
 
     if (version !== cacheVersion) {
       return;
     }
-
-    try {
-      currentVideoSrc = directUrl;
-      await setVideoSource(directUrl);
-    } catch (err) {
-      console.warn('Direct video load failed, using stream proxy:', err);
-      const streamUrl = makeStreamUrl();
-      currentVideoSrc = streamUrl;
-      await setVideoSource(streamUrl);
+    if (!source) {
+      status.textContent = '没有更早的播放记录';
+      loading.classList.add('hidden');
+      if (!video.paused) {
+        resumePreload();
+      }
+      return;
     }
 
+    revokeObjectUrlIfNeeded(currentVideoSrc);
+    currentVideoSrc = null;
+
+    await playSource(source);
+
     if (version !== cacheVersion) {
       return;
     }
 
     await playVideoEl();
-    startPreloadLoop();
+    resumePreload();
     status.textContent = '';
     status.classList.remove('error');
     consecutiveErrors = 0;
@@ -390,6 +515,7 @@ async function loadVideo() {
   } finally {
     isLoading = false;
     nextBtn.disabled = false;
+    prevBtn.disabled = false;
     if (shouldAutoSkip) {
       return;
     }
@@ -407,21 +533,22 @@ async function loadImage() {
     revokeObjectUrlIfNeeded(currentImageSrc);
     currentImageSrc = null;
     imageStatus.textContent = '加载中...';
-    const imageUrl = await fetchImageUrl();
+    const preloaded = await tryPopPreloadedImage();
+    const source = preloaded || await fetchImageSource();
 
     try {
-      currentImageSrc = imageUrl;
-      await setImageSource(imageUrl);
+      currentImageSrc = source.url;
+      await setImageSource(source.url);
     } catch (err) {
-      console.warn('Direct image load failed, using blob proxy:', err);
-      const blobUrl = await fetchImageAsBlobByUrl(imageUrl);
-      currentImageSrc = blobUrl;
-      await setImageSource(blobUrl);
+      console.warn('Direct image load failed, using stream proxy:', err);
+      currentImageSrc = source.streamUrl;
+      await setImageSource(source.streamUrl);
     }
 
     imageStatus.textContent = '';
     imageStatus.classList.remove('error');
     imageLoading.classList.add('hidden');
+    triggerImagePreload();
   } catch (err) {
     console.error('Image error:', err);
     const errMsg = err?.message || err;
@@ -453,7 +580,7 @@ async function initImage() {
   }
 }
 
-function clearImageCache(showStatus = true) {
+async function clearImageCache(showStatus = true) {
   revokeObjectUrlIfNeeded(currentImageSrc);
   currentImageSrc = null;
   image.removeAttribute('src');
@@ -462,13 +589,19 @@ function clearImageCache(showStatus = true) {
     imageStatus.classList.remove('error');
     imageStatus.textContent = '缓存已清理';
   }
+
+  try {
+    await window.__TAURI__.core.invoke('clear_image_preload_queue');
+  } catch (err) {
+    console.warn('clear_image_preload_queue failed:', err);
+  }
 }
 
 async function clearVideoCache(showStatus = true) {
   cacheVersion += 1;
   consecutiveErrors = 0;
   clearAutoSkipTimer();
-  stopPreloadLoop();
+  pausePreload();
 
   revokeObjectUrlIfNeeded(currentVideoSrc);
   currentVideoSrc = null;
@@ -487,9 +620,11 @@ async function clearVideoCache(showStatus = true) {
   } catch (err) {
     console.warn('clear_preload_queue failed:', err);
   }
+  renderUpNext();
 }
 
-nextBtn.addEventListener('click', loadVideo);
+nextBtn.addEventListener('click', () => loadVideo());
+prevBtn.addEventListener('click', () => loadVideo(() => window.__TAURI__.core.invoke('history_back')));
 
 video.addEventListener('ended', () => {
   if (autoPlayCheckbox.checked) {
@@ -500,6 +635,7 @@ video.addEventListener('ended', () => {
 nextImageBtn.addEventListener('click', loadImage);
 
 async function init() {
+  subscribePreloadEvents();
   loading.classList.remove('hidden');
   status.textContent = '加载首个视频...';
   status.classList.remove('error');
@@ -514,25 +650,17 @@ async function init() {
 
     revokeObjectUrlIfNeeded(currentVideoSrc);
     currentVideoSrc = null;
-    const directUrl = await fetchVideoUrl();
-    console.log('Init video URL:', directUrl);
+    const source = await fetchVideoSource();
+    console.log('Init video URL:', source.url);
 
     if (version !== cacheVersion) {
       return;
     }
 
-    try {
-      currentVideoSrc = directUrl;
-      await setVideoSource(directUrl);
-    } catch (err) {
-      console.warn('Direct video load failed, using stream proxy:', err);
-      const streamUrl = makeStreamUrl();
-      currentVideoSrc = streamUrl;
-      await setVideoSource(streamUrl);
-    }
+    await playSource(source);
 
     await playVideoEl();
-    startPreloadLoop();
+    resumePreload();
     status.textContent = '';
     status.classList.remove('error');
     consecutiveErrors = 0;
@@ -563,32 +691,6 @@ if (window.__TAURI__) {
 // 设置功能
 // ============================================================
 
-const CATEGORY_COMMANDS = {
-  video: {
-    getCategories: 'get_categories',
-    getCurrentCategory: 'get_current_category',
-    setCurrentCategory: 'set_current_category',
-    addCategory: 'add_custom_category',
-    addApi: 'add_custom_api',
-    deleteApi: 'delete_custom_api',
-    deleteCategory: 'delete_custom_category',
-    clearCache: 'clear_preload_queue',
-  },
-  image: {
-    getCategories: 'get_image_categories',
-    getCurrentCategory: 'get_current_image_category',
-    setCurrentCategory: 'set_current_image_category',
-    addCategory: 'add_custom_image_category',
-    addApi: 'add_custom_image_api',
-    deleteApi: 'delete_custom_image_api',
-    deleteCategory: 'delete_custom_image_category',
-  },
-};
-
-function getCommandsForMode(mode) {
-  return CATEGORY_COMMANDS[mode] || CATEGORY_COMMANDS.video;
-}
-
 function setSettingsMode(mode) {
   settingsMode = mode;
   settingsTabs.forEach(tab => {
@@ -600,19 +702,14 @@ function setSettingsMode(mode) {
   if (clearCacheBtn) {
     clearCacheBtn.textContent = mode === 'image' ? '清理图片缓存' : '清理视频缓存';
   }
-  newCategoryName.classList.add('hidden');
-  newCategoryName.value = '';
-  newApiName.value = '';
-  newApiUrl.value = '';
-  categorySelect.value = '';
+  clearApiForm();
   renderCategoryList(mode);
 }
 
 async function renderCategoryList(mode = settingsMode) {
   try {
-    const commands = getCommandsForMode(mode);
-    const categories = await window.__TAURI__.core.invoke(commands.getCategories);
-    const currentCategory = await window.__TAURI__.core.invoke(commands.getCurrentCategory);
+    const categories = await window.__TAURI__.core.invoke('get_categories', { media: mode });
+    const currentCategory = await window.__TAURI__.core.invoke('get_current_category', { media: mode });
 
     // 随机选项
     const randomHtml = `
@@ -642,7 +739,7 @@ async function renderCategoryList(mode = settingsMode) {
         <div class="category-endpoints" data-id="${cat.id}">
           ${cat.endpoints.map(ep => `
             <div class="endpoint-item">
-              <span class="endpoint-name">${ep.name}</span>
+              <span class="endpoint-name">${ep.name} ${ep.kind ? `<span class="api-badge">${ep.kind}</span>` : ''} ${ep.extract ? '<span class="api-badge">规则</span>' : ''}</span>
               <span class="endpoint-url">${ep.url}</span>
               ${!ep.builtin ? `<button class="endpoint-delete" data-id="${ep.id}">×</button>` : ''}
             </div>
@@ -665,11 +762,15 @@ async function renderCategoryList(mode = settingsMode) {
       header.addEventListener('click', async (e) => {
         if (e.target.closest('.category-toggle') || e.target.closest('.category-delete')) return;
         const categoryId = header.closest('.category-item').dataset.id;
-        await window.__TAURI__.core.invoke(commands.setCurrentCategory, { categoryId });
+        // 视频预加载队列由后端按新分类筛选并补满
+        await window.__TAURI__.core.invoke('set_current_category', { media: mode, categoryId });
         if (mode === 'video') {
-          await window.__TAURI__.core.invoke(commands.clearCache);
-        } else if (activePanel === 'image') {
-          loadImage();
+          renderUpNext();
+        } else {
+          await window.__TAURI__.core.invoke('clear_image_preload_queue');
+          if (activePanel === 'image') {
+            loadImage();
+          }
         }
         renderCategoryList(mode);
       });
@@ -692,7 +793,7 @@ async function renderCategoryList(mode = settingsMode) {
         e.stopPropagation();
         if (!confirm('确定删除该分类及其所有接口？')) return;
         try {
-          await window.__TAURI__.core.invoke(commands.deleteCategory, { categoryId: btn.dataset.id });
+          await window.__TAURI__.core.invoke('delete_custom_category', { media: mode, categoryId: btn.dataset.id });
           renderCategoryList(mode);
         } catch (err) {
           alert(err);
@@ -705,7 +806,7 @@ async function renderCategoryList(mode = settingsMode) {
       btn.addEventListener('click', async (e) => {
         e.stopPropagation();
         try {
-          await window.__TAURI__.core.invoke(commands.deleteApi, { apiId: btn.dataset.id });
+          await window.__TAURI__.core.invoke('delete_custom_api', { media: mode, apiId: btn.dataset.id });
           renderCategoryList(mode);
         } catch (err) {
           alert(err);
@@ -717,6 +818,44 @@ async function renderCategoryList(mode = settingsMode) {
   }
 }
 
+function clearApiForm() {
+  newApiName.value = '';
+  newApiUrl.value = '';
+  newApiKind.value = 'auto';
+  newApiPath.value = '';
+  newApiSuccess.value = '';
+  newApiRegex.value = '';
+  newCategoryName.value = '';
+  newCategoryName.classList.add('hidden');
+  categorySelect.value = '';
+}
+
+// 提取规则：路径 / 成功条件（字段=值）/ 正则，全部为空时不设置
+function buildExtractRule() {
+  const path = newApiPath.value.trim();
+  const regex = newApiRegex.value.trim();
+  const successText = newApiSuccess.value.trim();
+  let success = null;
+
+  if (successText) {
+    const idx = successText.indexOf('=');
+    if (idx <= 0) {
+      throw new Error('成功条件格式应为 字段=值，如 code=200');
+    }
+    const raw = successText.slice(idx + 1).trim();
+    let equals;
+    try {
+      equals = JSON.parse(raw);
+    } catch {
+      equals = raw;
+    }
+    success = { path: successText.slice(0, idx).trim(), equals };
+  }
+
+  if (!path && !regex && !success) return null;
+  return { path: path || null, success, regex: regex || null };
+}
+
 // 分类选择切换
 categorySelect.addEventListener('change', () => {
   if (categorySelect.value === '__new__') {
@@ -731,6 +870,8 @@ categorySelect.addEventListener('change', () => {
 settingsBtn.addEventListener('click', () => {
   settingsModal.classList.remove('hidden');
   setSettingsMode(activePanel);
+  loadPreloadSettings();
+  loadLibraryDir();
 });
 
 settingsTabs.forEach(tab => {
@@ -753,7 +894,7 @@ addApiBtn.addEventListener('click', async () => {
   const name = newApiName.value.trim();
   const url = newApiUrl.value.trim();
   let categoryId = categorySelect.value;
-  const commands = getCommandsForMode(settingsMode);
+  const media = settingsMode;
 
   if (!name || !url) {
     alert('请填写接口名称和URL');
@@ -768,7 +909,7 @@ addApiBtn.addEventListener('click', async () => {
         alert('请填写新分类名称');
         return;
       }
-      const newCat = await window.__TAURI__.core.invoke(commands.addCategory, { name: catName });
+      const newCat = await window.__TAURI__.core.invoke('add_custom_category', { media, name: catName });
       categoryId = newCat.id;
     }
 
@@ -777,18 +918,16 @@ addApiBtn.addEventListener('click', async () => {
       return;
     }
 
-    await window.__TAURI__.core.invoke(commands.addApi, { categoryId, name, url });
+    const extract = buildExtractRule();
+    const kind = newApiKind.value;
+    await window.__TAURI__.core.invoke('add_custom_api', { media, categoryId, name, url, extract, kind });
 
     // 清空表单
-    newApiName.value = '';
-    newApiUrl.value = '';
-    newCategoryName.value = '';
-    newCategoryName.classList.add('hidden');
-    categorySelect.value = '';
+    clearApiForm();
 
     renderCategoryList(settingsMode);
   } catch (err) {
-    alert(err);
+    alert(err?.message || err);
   }
 });
 
@@ -800,8 +939,338 @@ clearCacheBtn.addEventListener('click', async () => {
   if (!confirm(confirmText)) return;
 
   if (isImageMode) {
-    clearImageCache();
+    await clearImageCache();
   } else {
     await clearVideoCache();
   }
 });
+
+function fillPreloadSettings(settings) {
+  videoDepthInput.value = settings.video_depth;
+  imageDepthInput.value = settings.image_depth;
+  concurrencyInput.value = settings.concurrency;
+  budgetInput.value = Math.round(settings.budget_bytes / MB);
+  dedupWindowInput.value = settings.dedup_window;
+  imageHashSelect.value = settings.image_hash;
+  imageDistanceInput.value = settings.image_distance;
+}
+
+async function loadPreloadSettings() {
+  try {
+    fillPreloadSettings(await window.__TAURI__.core.invoke('get_preload_settings'));
+  } catch (err) {
+    console.warn('get_preload_settings failed:', err);
+  }
+}
+
+savePreloadBtn.addEventListener('click', async () => {
+  const settings = {
+    video_depth: parseInt(videoDepthInput.value, 10) || 0,
+    image_depth: parseInt(imageDepthInput.value, 10) || 0,
+    concurrency: parseInt(concurrencyInput.value, 10) || 1,
+    budget_bytes: (parseInt(budgetInput.value, 10) || 0) * MB,
+    dedup_window: parseInt(dedupWindowInput.value, 10) || 0,
+    image_hash: imageHashSelect.value,
+    image_distance: parseInt(imageDistanceInput.value, 10) || 0,
+  };
+
+  try {
+    // 后端会把超出范围的值收敛，回填实际生效的设置
+    fillPreloadSettings(await window.__TAURI__.core.invoke('set_preload_settings', { settings }));
+    alert('已保存');
+  } catch (err) {
+    alert(err);
+  }
+});
+
+// ============================================================
+// 播放记录
+// ============================================================
+
+const HISTORY_LIMIT = 100;
+
+function formatPlayedAt(millis) {
+  return new Date(millis).toLocaleString();
+}
+
+async function renderHistory() {
+  let items;
+  try {
+    items = await window.__TAURI__.core.invoke('search_history', {
+      query: historySearch.value,
+      limit: HISTORY_LIMIT,
+    });
+  } catch (err) {
+    console.warn('search_history failed:', err);
+    return;
+  }
+
+  historyList.replaceChildren(...items.map((item) => {
+    const row = document.createElement('li');
+    row.className = 'up-next-item clickable';
+    row.title = item.url;
+
+    const info = document.createElement('div');
+    info.className = 'up-next-info';
+    const name = document.createElement('span');
+    name.className = 'up-next-name';
+    name.textContent = `${item.endpointName} · ${formatPlayedAt(item.playedAt)}`;
+    const meta = document.createElement('span');
+    meta.className = 'up-next-meta';
+    meta.textContent = item.cachedBytes !== null
+      ? `已缓存 ${formatSize(item.cachedBytes)} · ${item.url}`
+      : item.url;
+    info.append(name, meta);
+    row.append(info);
+
+    row.addEventListener('click', () => {
+      historyModal.classList.add('hidden');
+      setActivePanel('video');
+      loadVideo(() => window.__TAURI__.core.invoke('replay_history', { id: item.id }));
+    });
+    return row;
+  }));
+}
+
+historyBtn.addEventListener('click', () => {
+  historyModal.classList.remove('hidden');
+  renderHistory();
+});
+
+closeHistory.addEventListener('click', () => {
+  historyModal.classList.add('hidden');
+});
+
+historyModal.addEventListener('click', (e) => {
+  if (e.target === historyModal) {
+    historyModal.classList.add('hidden');
+  }
+});
+
+historySearch.addEventListener('input', renderHistory);
+
+clearHistoryBtn.addEventListener('click', async () => {
+  if (!confirm('确定清空全部播放记录？')) return;
+  try {
+    await window.__TAURI__.core.invoke('clear_history');
+  } catch (err) {
+    alert(err);
+  }
+  renderHistory();
+});
+
+// ============================================================
+// 收藏
+// ============================================================
+
+async function saveFavorite(command, btn, statusEl) {
+  btn.disabled = true;
+  try {
+    const favorite = await window.__TAURI__.core.invoke(command);
+    statusEl.classList.remove('error');
+    statusEl.textContent = `已收藏（${formatSize(favorite.size)}）`;
+  } catch (err) {
+    statusEl.classList.add('error');
+    statusEl.textContent = `收藏失败: ${err?.message || err}`;
+  } finally {
+    btn.disabled = false;
+  }
+}
+
+saveVideoBtn.addEventListener('click', () => saveFavorite('save_current_video', saveVideoBtn, status));
+saveImageBtn.addEventListener('click', () => saveFavorite('save_current_image', saveImageBtn, imageStatus));
+
+async function playFavorite(favorite) {
+  favoritesModal.classList.add('hidden');
+  if (favorite.media === 'video') {
+    setActivePanel('video');
+    loadVideo(() => window.__TAURI__.core.invoke('play_favorite', { id: favorite.id }));
+    return;
+  }
+
+  setActivePanel('image');
+  try {
+    const source = await window.__TAURI__.core.invoke('play_favorite', { id: favorite.id });
+    revokeObjectUrlIfNeeded(currentImageSrc);
+    currentImageSrc = source.url;
+    await setImageSource(source.url);
+    imageStatus.textContent = '';
+    imageStatus.classList.remove('error');
+  } catch (err) {
+    imageStatus.textContent = `加载失败: ${err?.message || err}`;
+    imageStatus.classList.add('error');
+  }
+}
+
+function favoriteButton(text, title, onClick) {
+  const btn = document.createElement('button');
+  btn.textContent = text;
+  btn.title = title;
+  btn.addEventListener('click', async (e) => {
+    e.stopPropagation();
+    try {
+      await onClick();
+    } catch (err) {
+      alert(err);
+    }
+    renderFavorites();
+  });
+  return btn;
+}
+
+// 开启时按相似分组列出收藏的图片，忽略类型与标签筛选
+let showingSimilar = false;
+
+async function renderFavorites() {
+  if (showingSimilar) {
+    renderSimilarFavorites();
+    return;
+  }
+
+  let favorites;
+  try {
+    favorites = await window.__TAURI__.core.invoke('list_favorites', {
+      media: favoritesMedia.value || null,
+      tag: favoritesTag.value,
+    });
+  } catch (err) {
+    console.warn('list_favorites failed:', err);
+    return;
+  }
+
+  favoritesList.replaceChildren(...favorites.map(favoriteRow));
+}
+
+async function renderSimilarFavorites() {
+  favoritesList.replaceChildren();
+  let groups;
+  try {
+    groups = await window.__TAURI__.core.invoke('list_similar_favorites');
+  } catch (err) {
+    console.warn('list_similar_favorites failed:', err);
+    return;
+  }
+
+  if (groups.length === 0) {
+    const empty = document.createElement('li');
+    empty.className = 'favorites-group';
+    empty.textContent = '没有相似的收藏图片';
+    favoritesList.replaceChildren(empty);
+    return;
+  }
+  favoritesList.replaceChildren(...groups.flatMap((group, index) => {
+    const header = document.createElement('li');
+    header.className = 'favorites-group';
+    header.textContent = `相似组 ${index + 1} · ${group.length} 张`;
+    return [header, ...group.map(favoriteRow)];
+  }));
+}
+
+function favoriteRow(favorite) {
+  const invoke = window.__TAURI__.core.invoke;
+  const row = document.createElement('li');
+  row.className = 'up-next-item clickable';
+  row.title = favorite.url;
+
+  const info = document.createElement('div');
+  info.className = 'up-next-info';
+  const name = document.createElement('span');
+  name.className = 'up-next-name';
+  const kind = favorite.media === 'video' ? '视频' : '图片';
+  name.textContent = `${kind} · ${favorite.endpointName} · ${formatPlayedAt(favorite.savedAt)}`;
+  const meta = document.createElement('span');
+  meta.className = 'up-next-meta';
+  const tags = favorite.tags.length > 0 ? ` · #${favorite.tags.join(' #')}` : '';
+  meta.textContent = `${favorite.contentType} · ${formatSize(favorite.size)}${tags}`;
+  info.append(name, meta);
+  row.append(info);
+
+  row.append(
+    favoriteButton('#', '编辑标签', async () => {
+      const input = prompt('标签（用逗号或空格分隔）', favorite.tags.join(' '));
+      if (input === null) return;
+      await invoke('set_favorite_tags', { id: favorite.id, tags: input.split(/[,，\s]+/) });
+    }),
+    favoriteButton('×', '删除', async () => {
+      if (!confirm('确定删除该收藏及其文件？')) return;
+      await invoke('remove_favorite', { id: favorite.id });
+    }),
+  );
+  row.addEventListener('click', () => playFavorite(favorite));
+  return row;
+}
+
+favoritesBtn.addEventListener('click', () => {
+  favoritesModal.classList.remove('hidden');
+  renderFavorites();
+});
+
+closeFavorites.addEventListener('click', () => {
+  favoritesModal.classList.add('hidden');
+});
+
+favoritesModal.addEventListener('click', (e) => {
+  if (e.target === favoritesModal) {
+    favoritesModal.classList.add('hidden');
+  }
+});
+
+similarFavoritesBtn.addEventListener('click', () => {
+  showingSimilar = !showingSimilar;
+  similarFavoritesBtn.classList.toggle('active', showingSimilar);
+  renderFavorites();
+});
+
+favoritesMedia.addEventListener('change', renderFavorites);
+favoritesTag.addEventListener('input', renderFavorites);
+
+async function loadLibraryDir() {
+  try {
+    libraryDirInput.value = await window.__TAURI__.core.invoke('get_library_dir');
+  } catch (err) {
+    console.warn('get_library_dir failed:', err);
+  }
+}
+
+saveLibraryDirBtn.addEventListener('click', async () => {
+  try {
+    await window.__TAURI__.core.invoke('set_library_dir', { path: libraryDirInput.value });
+    alert('已保存');
+  } catch (err) {
+    alert(err);
+  }
+});
+
+exportCatalogBtn.addEventListener('click', async () => {
+  const path = catalogPath.value.trim();
+  if (!path) {
+    alert('请填写文件路径');
+    return;
+  }
+
+  try {
+    await window.__TAURI__.core.invoke('export_api_catalog', { path });
+    alert('导出完成');
+  } catch (err) {
+    alert(err);
+  }
+});
+
+importCatalogBtn.addEventListener('click', async () => {
+  const path = catalogPath.value.trim();
+  const mode = importMode.value;
+  if (!path) {
+    alert('请填写文件路径');
+    return;
+  }
+  if (mode === 'replace' && !confirm('确定用导入的清单替换全部自定义接口？')) return;
+
+  try {
+    const summary = await window.__TAURI__.core.invoke('import_api_catalog', { path, mode });
+    alert(`导入完成：新增 ${summary.categoriesAdded} 个分类、${summary.endpointsAdded} 个接口，`
+      + `跳过重复 ${summary.duplicatesSkipped} 个、无效 ${summary.invalidSkipped} 个`);
+    renderCategoryList(settingsMode);
+  } catch (err) {
+    alert(err);
+  }
+});